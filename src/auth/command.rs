@@ -0,0 +1,315 @@
+//! External token commands
+//!
+//! Teams often already have a CLI that prints tokens (cloud SDKs, `vault`,
+//! SSO helpers). With `--auth command`, the `--credential-command` is run
+//! like a kubectl exec credential plugin: it prints JSON with the token and
+//! its expiry, e.g.
+//!
+//! ```json
+//! {"access_token": "eyJ...", "expires_at": 1767225600}
+//! ```
+//!
+//! `expires_at` may also be an RFC 3339 timestamp, and kubectl's
+//! `ExecCredential` format (`status.token`, `status.expirationTimestamp`) is
+//! understood as well. The token is cached until it expires and the command
+//! runs again when the backend rejects it with 401. The command's stderr is
+//! passed through, so it can prompt on the terminal (e.g. for a PIN); its
+//! stdin is not connected because the proxy's stdin carries MCP messages.
+
+use super::credential::shell_command;
+use super::{AuthProvider, Retry};
+use crate::config::Config;
+use crate::error::{ProxyError, Result};
+use crate::oidc::jwt;
+use crate::oidc::token::TOKEN_EXPIRY_BUFFER_SECS;
+use async_trait::async_trait;
+use reqwest::header::AUTHORIZATION;
+use reqwest::{Request, Response, StatusCode};
+use serde_json::{Map, Value};
+use std::process::Stdio;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+/// Default time the command may take (including interactive prompts)
+pub const DEFAULT_TIMEOUT_SECS: u64 = 120;
+
+/// Token printed by the command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandToken {
+    pub access_token: String,
+    pub token_type: String,
+    /// Expiry as Unix timestamp (`None` = until rejected)
+    pub expires_at: Option<u64>,
+}
+
+impl CommandToken {
+    /// Parse the command output
+    pub fn parse(output: &str) -> Result<Self> {
+        let json: Value = serde_json::from_str(output.trim()).map_err(|e| {
+            ProxyError::Token(format!("Token command output is not valid JSON: {}", e))
+        })?;
+
+        // kubectl ExecCredential
+        if let Some(status) = json.get("status") {
+            return Ok(Self {
+                access_token: string(status, "token")?,
+                token_type: "Bearer".to_string(),
+                expires_at: timestamp(status.get("expirationTimestamp"))?,
+            });
+        }
+
+        Ok(Self {
+            access_token: string(&json, "access_token")?,
+            token_type: json
+                .get("token_type")
+                .and_then(Value::as_str)
+                .unwrap_or("Bearer")
+                .to_string(),
+            expires_at: timestamp(json.get("expires_at"))?,
+        })
+    }
+
+    /// Check if the token is valid (not about to expire)
+    pub fn is_valid(&self) -> bool {
+        match self.expires_at {
+            Some(expires_at) => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
+                now < expires_at.saturating_sub(TOKEN_EXPIRY_BUFFER_SECS)
+            }
+            None => true,
+        }
+    }
+}
+
+/// Non-empty string member
+fn string(json: &Value, name: &str) -> Result<String> {
+    json.get(name)
+        .and_then(Value::as_str)
+        .filter(|value| !value.is_empty())
+        .map(String::from)
+        .ok_or_else(|| ProxyError::Token(format!("Token command output has no '{}'", name)))
+}
+
+/// Unix timestamp from a number or an RFC 3339 string
+fn timestamp(value: Option<&Value>) -> Result<Option<u64>> {
+    match value {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::Number(number)) => number
+            .as_u64()
+            .map(Some)
+            .ok_or_else(|| ProxyError::Token(format!("Invalid token expiry: {}", number))),
+        Some(Value::String(text)) => chrono::DateTime::parse_from_rfc3339(text)
+            .map(|time| Some(time.timestamp().max(0) as u64))
+            .map_err(|e| ProxyError::Token(format!("Invalid token expiry '{}': {}", text, e))),
+        Some(other) => Err(ProxyError::Token(format!(
+            "Invalid token expiry: {}",
+            other
+        ))),
+    }
+}
+
+/// Auth provider running an external token command
+pub struct CommandAuth {
+    command: String,
+    timeout: Duration,
+    /// Cached token; the lock also makes concurrent requests wait for one run
+    token: Mutex<Option<CommandToken>>,
+}
+
+impl CommandAuth {
+    /// Create the provider for `--credential-command`
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let command = config.credential_command.clone().ok_or_else(|| {
+            ProxyError::Config("--auth command requires --credential-command".to_string())
+        })?;
+
+        Ok(Self {
+            command,
+            timeout: Duration::from_secs(
+                config
+                    .credential_command_timeout
+                    .unwrap_or(DEFAULT_TIMEOUT_SECS),
+            ),
+            token: Mutex::new(None),
+        })
+    }
+
+    /// Cached token, running the command if there is none or it expired
+    pub async fn token(&self) -> Result<CommandToken> {
+        let mut cached = self.token.lock().await;
+        match *cached {
+            Some(ref token) if token.is_valid() => Ok(token.clone()),
+            _ => {
+                let token = self.run().await?;
+                *cached = Some(token.clone());
+                Ok(token)
+            }
+        }
+    }
+
+    /// Run the command again unless the rejected token was already replaced
+    async fn renew(&self, rejected_token: &str) -> Result<()> {
+        let mut cached = self.token.lock().await;
+        let replaced = cached
+            .as_ref()
+            .is_some_and(|token| token.access_token != rejected_token && token.is_valid());
+        if !replaced {
+            *cached = Some(self.run().await?);
+        }
+        Ok(())
+    }
+
+    async fn run(&self) -> Result<CommandToken> {
+        debug!("Running token command: {}", self.command);
+
+        let child = shell_command(&self.command)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| {
+                ProxyError::Token(format!(
+                    "Failed to run token command '{}': {}",
+                    self.command, e
+                ))
+            })?;
+
+        // The child is killed when the timeout drops it
+        let output = tokio::time::timeout(self.timeout, child.wait_with_output())
+            .await
+            .map_err(|_| {
+                ProxyError::Timeout(format!(
+                    "Token command '{}' did not finish within {} seconds",
+                    self.command,
+                    self.timeout.as_secs()
+                ))
+            })??;
+
+        if !output.status.success() {
+            return Err(ProxyError::Token(format!(
+                "Token command '{}' failed ({})",
+                self.command, output.status
+            )));
+        }
+
+        CommandToken::parse(&String::from_utf8_lossy(&output.stdout))
+    }
+}
+
+#[async_trait]
+impl AuthProvider for CommandAuth {
+    async fn authorize(&self, req: &mut Request) -> Result<()> {
+        let token = self.token().await?;
+        let mut value = format!("{} {}", token.token_type, token.access_token)
+            .parse::<reqwest::header::HeaderValue>()
+            .map_err(|e| ProxyError::Token(format!("Invalid token from command: {}", e)))?;
+        value.set_sensitive(true);
+        req.headers_mut().insert(AUTHORIZATION, value);
+        Ok(())
+    }
+
+    async fn on_response(
+        &self,
+        req: &Request,
+        response: &Response,
+        renewed: bool,
+    ) -> Result<Retry> {
+        if renewed || response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(Retry::No);
+        }
+
+        warn!("Received 401 Unauthorized, running the token command again");
+        let rejected = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split_once(' '))
+            .map(|(_, token)| token)
+            .unwrap_or_default();
+        self.renew(rejected).await?;
+        Ok(Retry::Renewed)
+    }
+
+    async fn claims(&self) -> Option<Map<String, Value>> {
+        let cached = self.token.lock().await;
+        jwt::decode_claims(&cached.as_ref()?.access_token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AuthMode;
+
+    #[test]
+    fn test_parse_command_output() {
+        let token =
+            CommandToken::parse(r#"{"access_token": "t1", "expires_at": 4102444800}"#).unwrap();
+        assert_eq!(token.access_token, "t1");
+        assert_eq!(token.token_type, "Bearer");
+        assert_eq!(token.expires_at, Some(4102444800));
+        assert!(token.is_valid());
+
+        let exec_credential = CommandToken::parse(
+            r#"{"kind": "ExecCredential", "status": {"token": "t2", "expirationTimestamp": "2000-01-01T00:00:00Z"}}"#,
+        )
+        .unwrap();
+        assert_eq!(exec_credential.access_token, "t2");
+        assert_eq!(exec_credential.expires_at, Some(946684800));
+        assert!(!exec_credential.is_valid());
+
+        assert!(CommandToken::parse("t3").is_err());
+        assert!(CommandToken::parse(r#"{"expires_at": 1}"#).is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command_token_cached_and_renewed() {
+        let counter = std::env::temp_dir().join(format!(
+            "authful-mcp-proxy-token-command-{}",
+            std::process::id()
+        ));
+        let config = Config {
+            auth: AuthMode::Command,
+            // Prints a new token on each run; stderr is passed through
+            credential_command: Some(format!(
+                r#"echo x >> {0}; echo "prompt" >&2; printf '{{"access_token": "token-%s"}}' $(wc -l < {0} | tr -d ' ')"#,
+                counter.display()
+            )),
+            ..Default::default()
+        };
+        let auth = CommandAuth::from_config(&config).unwrap();
+
+        let first = auth.token().await;
+        let cached = auth.token().await;
+        auth.renew("token-1").await.unwrap();
+        // Already replaced, so no third run
+        auth.renew("token-1").await.unwrap();
+        let renewed = auth.token().await;
+        std::fs::remove_file(&counter).unwrap();
+
+        assert_eq!(first.unwrap().access_token, "token-1");
+        assert_eq!(cached.unwrap().access_token, "token-1");
+        assert_eq!(renewed.unwrap().access_token, "token-2");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command_timeout() {
+        let config = Config {
+            auth: AuthMode::Command,
+            credential_command: Some("sleep 5".to_string()),
+            credential_command_timeout: Some(0),
+            ..Default::default()
+        };
+        let error = CommandAuth::from_config(&config)
+            .unwrap()
+            .token()
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ProxyError::Timeout(_)), "{}", error);
+    }
+}
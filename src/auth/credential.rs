@@ -0,0 +1,270 @@
+//! Static credentials
+//!
+//! Bearer tokens, API keys and Basic passwords are read once at startup from
+//! the configuration, a file or the output of a shell command (e.g. a
+//! password manager CLI).
+
+use super::AuthProvider;
+use crate::config::{AuthMode, Config};
+use crate::error::{ProxyError, Result};
+use crate::oidc::jwt;
+use async_trait::async_trait;
+use base64::Engine;
+use reqwest::header::{HeaderName, HeaderValue, AUTHORIZATION};
+use reqwest::Request;
+use serde_json::{Map, Value};
+use std::path::PathBuf;
+
+/// Default header for `--auth api-key`
+pub const DEFAULT_API_KEY_HEADER: &str = "X-API-Key";
+
+/// Where the credential comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CredentialSource {
+    Value(String),
+    File(PathBuf),
+    Command(String),
+}
+
+impl CredentialSource {
+    /// Credential source of the configuration, if any
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config
+            .credential
+            .clone()
+            .map(Self::Value)
+            .or_else(|| config.credential_file.clone().map(Self::File))
+            .or_else(|| config.credential_command.clone().map(Self::Command))
+    }
+
+    /// Read the credential (surrounding whitespace removed)
+    pub async fn load(&self) -> Result<String> {
+        let credential = match self {
+            Self::Value(value) => value.clone(),
+            Self::File(path) => tokio::fs::read_to_string(path).await.map_err(|e| {
+                ProxyError::Config(format!(
+                    "Failed to read credential file {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?,
+            Self::Command(command) => run_command(command).await?,
+        };
+
+        let credential = credential.trim();
+        if credential.is_empty() {
+            return Err(ProxyError::Config(format!(
+                "Empty credential from {}",
+                self.describe()
+            )));
+        }
+        Ok(credential.to_string())
+    }
+
+    /// Description for messages (never the credential itself)
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Value(_) => "--credential".to_string(),
+            Self::File(path) => format!("file {}", path.display()),
+            Self::Command(command) => format!("command '{}'", command),
+        }
+    }
+}
+
+/// Command running `command` with the platform shell
+pub(crate) fn shell_command(command: &str) -> tokio::process::Command {
+    #[cfg(windows)]
+    let mut cmd = {
+        let mut cmd = tokio::process::Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    };
+    #[cfg(not(windows))]
+    let mut cmd = {
+        let mut cmd = tokio::process::Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    };
+    cmd.stdin(std::process::Stdio::null()).kill_on_drop(true);
+    cmd
+}
+
+/// Run a command with the platform shell and return its standard output
+async fn run_command(command: &str) -> Result<String> {
+    let output = shell_command(command).output().await.map_err(|e| {
+        ProxyError::Config(format!(
+            "Failed to run credential command '{}': {}",
+            command, e
+        ))
+    })?;
+
+    if !output.status.success() {
+        return Err(ProxyError::Config(format!(
+            "Credential command '{}' failed ({}): {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    String::from_utf8(output.stdout).map_err(|_| {
+        ProxyError::Config(format!(
+            "Credential command '{}' printed invalid UTF-8",
+            command
+        ))
+    })
+}
+
+/// Fixed credential header for the `bearer`, `api-key` and `basic` modes
+pub struct StaticAuth {
+    header: HeaderName,
+    value: HeaderValue,
+    /// Claims of a JWT bearer token
+    claims: Option<Map<String, Value>>,
+}
+
+impl StaticAuth {
+    /// Load the credential and build the header of the configured mode
+    pub async fn from_config(config: &Config) -> Result<Self> {
+        let source = CredentialSource::from_config(config).ok_or_else(|| {
+            ProxyError::Config(format!(
+                "--auth {} requires --credential, --credential-file or --credential-command",
+                config.auth.name()
+            ))
+        })?;
+        let credential = source.load().await?;
+
+        Self::new(config, &credential)
+    }
+
+    /// Header for the configured mode and a loaded credential
+    pub fn new(config: &Config, credential: &str) -> Result<Self> {
+        let (header, value) = match config.auth {
+            AuthMode::Bearer => (AUTHORIZATION, format!("Bearer {}", credential)),
+            AuthMode::ApiKey => {
+                let name = config
+                    .api_key_header
+                    .as_deref()
+                    .unwrap_or(DEFAULT_API_KEY_HEADER);
+                let header = HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+                    ProxyError::Config(format!("Invalid API key header name '{}'", name))
+                })?;
+                (header, credential.to_string())
+            }
+            AuthMode::Basic => {
+                let user = config.basic_user.as_deref().ok_or_else(|| {
+                    ProxyError::Config("--auth basic requires --basic-user".to_string())
+                })?;
+                let encoded = base64::engine::general_purpose::STANDARD
+                    .encode(format!("{}:{}", user, credential));
+                (AUTHORIZATION, format!("Basic {}", encoded))
+            }
+            AuthMode::Oidc | AuthMode::Command | AuthMode::None => {
+                return Err(ProxyError::Config(format!(
+                    "Static credentials are not used with --auth {}",
+                    config.auth.name()
+                )))
+            }
+        };
+
+        let mut value = HeaderValue::from_str(&value).map_err(|_| {
+            ProxyError::Config("Credential contains characters not allowed in headers".to_string())
+        })?;
+        value.set_sensitive(true);
+
+        let claims = match config.auth {
+            AuthMode::Bearer => jwt::decode_claims(credential),
+            _ => None,
+        };
+
+        Ok(Self {
+            header,
+            value,
+            claims,
+        })
+    }
+
+    /// Name of the header carrying the credential
+    pub fn header(&self) -> &HeaderName {
+        &self.header
+    }
+}
+
+#[async_trait]
+impl AuthProvider for StaticAuth {
+    async fn authorize(&self, req: &mut Request) -> Result<()> {
+        req.headers_mut()
+            .insert(self.header.clone(), self.value.clone());
+        Ok(())
+    }
+
+    async fn claims(&self) -> Option<Map<String, Value>> {
+        self.claims.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(auth: AuthMode) -> Config {
+        Config {
+            backend_url: "https://backend.example.com/mcp".to_string(),
+            auth,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_static_auth_headers() {
+        let mut req = Request::new(
+            reqwest::Method::POST,
+            "https://backend.example.com/mcp".parse().unwrap(),
+        );
+
+        let bearer = StaticAuth::new(&config(AuthMode::Bearer), "token-1").unwrap();
+        bearer.authorize(&mut req).await.unwrap();
+        assert_eq!(req.headers()[AUTHORIZATION], "Bearer token-1");
+
+        let mut api_key = config(AuthMode::ApiKey);
+        api_key.api_key_header = Some("X-Api-Token".to_string());
+        let api_key = StaticAuth::new(&api_key, "key-1").unwrap();
+        api_key.authorize(&mut req).await.unwrap();
+        assert_eq!(req.headers()["x-api-token"], "key-1");
+
+        let mut basic = config(AuthMode::Basic);
+        basic.basic_user = Some("Aladdin".to_string());
+        let basic = StaticAuth::new(&basic, "open sesame").unwrap();
+        basic.authorize(&mut req).await.unwrap();
+        assert_eq!(
+            req.headers()[AUTHORIZATION],
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+
+        assert!(StaticAuth::new(&config(AuthMode::Bearer), "line\nbreak").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_load_credential_from_file_and_command() {
+        let path = std::env::temp_dir().join(format!(
+            "authful-mcp-proxy-credential-{}",
+            std::process::id()
+        ));
+        std::fs::write(&path, "file-secret\n").unwrap();
+        let loaded = CredentialSource::File(path.clone()).load().await;
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), "file-secret");
+
+        let command = CredentialSource::Command("echo command-secret".to_string());
+        assert_eq!(command.load().await.unwrap(), "command-secret");
+
+        let failing = CredentialSource::Command("echo denied >&2; exit 3".to_string());
+        let error = failing.load().await.unwrap_err().to_string();
+        assert!(error.contains("denied"), "{}", error);
+
+        assert!(CredentialSource::Value("  ".to_string())
+            .load()
+            .await
+            .is_err());
+    }
+}
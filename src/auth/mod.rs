@@ -0,0 +1,97 @@
+//! Backend authentication
+//!
+//! `AuthMiddleware` adds credentials to backend requests through an
+//! `AuthProvider`. The provider is selected with `--auth`:
+//!
+//! - `oidc` (default): interactive OIDC login, see [`crate::oidc::OidcClient`]
+//! - `bearer`: static bearer token
+//! - `api-key`: static API key in a custom header
+//! - `basic`: HTTP Basic authentication
+//! - `command`: bearer token from an external command, see [`command`]
+//! - `none`: no credentials (e.g. when requests are only signed with SigV4)
+//!
+//! The static modes read their credential from `--credential`,
+//! `--credential-file` or `--credential-command`. Only the `oidc` mode
+//! contacts an OIDC provider.
+
+pub mod command;
+pub mod credential;
+mod oidc;
+
+pub use command::CommandAuth;
+pub use credential::{CredentialSource, StaticAuth};
+
+use crate::config::{AuthMode, Config};
+use crate::error::Result;
+use crate::oidc::userinfo::UserIdentity;
+use crate::oidc::OidcClient;
+use async_trait::async_trait;
+use reqwest::{Request, Response};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+/// What to do after the backend responded to an authorized request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retry {
+    /// Return the response
+    No,
+    /// Authorize and send the request again with unchanged credentials
+    /// (e.g. after the backend issued a new DPoP nonce)
+    Resend,
+    /// Credentials were renewed; authorize and send the request again
+    Renewed,
+}
+
+/// Source of credentials for backend requests
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    /// Add credentials to a backend request
+    async fn authorize(&self, req: &mut Request) -> Result<()>;
+
+    /// Inspect the response to an authorized request
+    ///
+    /// `renewed` tells whether the credentials were already renewed for this
+    /// request; the middleware retries at most once per kind of `Retry`.
+    async fn on_response(
+        &self,
+        _req: &Request,
+        _response: &Response,
+        _renewed: bool,
+    ) -> Result<Retry> {
+        Ok(Retry::No)
+    }
+
+    /// Identity the proxy acts as, if known
+    async fn identity(&self) -> Option<UserIdentity> {
+        None
+    }
+
+    /// Claims of the current token for header templates, if known
+    async fn claims(&self) -> Option<Map<String, Value>> {
+        None
+    }
+}
+
+/// Provider for `--auth none`: requests are sent without credentials
+pub struct NoAuth;
+
+#[async_trait]
+impl AuthProvider for NoAuth {
+    async fn authorize(&self, _req: &mut Request) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Create the auth provider selected by the configuration
+///
+/// Only the `oidc` mode runs OIDC discovery.
+pub async fn from_config(config: &Config) -> Result<Arc<dyn AuthProvider>> {
+    Ok(match config.auth {
+        AuthMode::Oidc => Arc::new(OidcClient::from_config(config).await?),
+        AuthMode::Bearer | AuthMode::ApiKey | AuthMode::Basic => {
+            Arc::new(StaticAuth::from_config(config).await?)
+        }
+        AuthMode::Command => Arc::new(CommandAuth::from_config(config)?),
+        AuthMode::None => Arc::new(NoAuth),
+    })
+}
@@ -0,0 +1,117 @@
+//! OIDC login as auth provider
+//!
+//! DPoP-bound tokens are sent with the `DPoP` scheme and a fresh proof per
+//! request; a `use_dpop_nonce` challenge is retried with the new nonce.
+//!
+//! Step-up challenges (`insufficient_scope`, `insufficient_user_authentication`)
+//! trigger a re-authorization with the required scopes and authentication
+//! level, any other 401 a token renewal, after which the request is retried.
+
+use super::{AuthProvider, Retry};
+use crate::error::{ProxyError, Result};
+use crate::oidc::challenge::AuthChallenge;
+use crate::oidc::userinfo::UserIdentity;
+use crate::oidc::{dpop, OidcClient};
+use async_trait::async_trait;
+use reqwest::header::AUTHORIZATION;
+use reqwest::{Request, Response, StatusCode};
+use serde_json::{Map, Value};
+use tracing::{debug, warn};
+
+#[async_trait]
+impl AuthProvider for OidcClient {
+    /// Set the Authorization header (and DPoP proof for DPoP-bound tokens)
+    async fn authorize(&self, req: &mut Request) -> Result<()> {
+        let token = self.get_token().await?;
+        let invalid = |e: reqwest::header::InvalidHeaderValue| {
+            ProxyError::Auth(format!("Invalid token: {}", e))
+        };
+
+        let dpop = match self.dpop() {
+            Some(dpop) if self.token_is_dpop_bound().await => Some(dpop),
+            _ => None,
+        };
+        let scheme = if dpop.is_some() { "DPoP" } else { "Bearer" };
+
+        req.headers_mut().insert(
+            AUTHORIZATION,
+            format!("{} {}", scheme, token).parse().map_err(invalid)?,
+        );
+
+        if let Some(dpop) = dpop {
+            let proof = dpop.proof(req.method().as_str(), req.url().as_str(), Some(&token))?;
+            req.headers_mut()
+                .insert(dpop::DPOP_HEADER, proof.parse().map_err(invalid)?);
+        }
+
+        Ok(())
+    }
+
+    async fn on_response(
+        &self,
+        req: &Request,
+        response: &Response,
+        renewed: bool,
+    ) -> Result<Retry> {
+        // DPoP nonce challenge - retry with the new nonce
+        if let Some(dpop) = self.dpop() {
+            let new_nonce = dpop.update_nonce(req.url().as_str(), response.headers());
+            if new_nonce
+                && response.status() == StatusCode::UNAUTHORIZED
+                && dpop::is_nonce_challenge(response.headers())
+            {
+                debug!("Backend requires a DPoP nonce, retrying");
+                return Ok(Retry::Resend);
+            }
+        }
+
+        if renewed {
+            return Ok(Retry::No);
+        }
+
+        // Step-up challenges - re-authorize and retry
+        if matches!(
+            response.status(),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+        ) {
+            if let Some(challenge) = AuthChallenge::from_headers(response.headers())
+                .filter(|challenge| challenge.requires_step_up())
+            {
+                warn!(
+                    "Received {} with '{}' challenge, re-authorizing and retrying",
+                    response.status(),
+                    challenge.error.as_deref().unwrap_or_default()
+                );
+                self.step_up(&challenge, &sent_token(req)).await?;
+                return Ok(Retry::Renewed);
+            }
+        }
+
+        // 401 Unauthorized - renew token (refresh or full auth flow) and retry
+        if response.status() == StatusCode::UNAUTHORIZED {
+            warn!("Received 401 Unauthorized, renewing token and retrying");
+            self.renew_token(&sent_token(req)).await?;
+            return Ok(Retry::Renewed);
+        }
+
+        Ok(Retry::No)
+    }
+
+    async fn identity(&self) -> Option<UserIdentity> {
+        OidcClient::identity(self).await
+    }
+
+    async fn claims(&self) -> Option<Map<String, Value>> {
+        OidcClient::claims(self).await
+    }
+}
+
+/// Access token sent with the request (without the scheme)
+fn sent_token(req: &Request) -> String {
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split_once(' '))
+        .map(|(_, token)| token.to_string())
+        .unwrap_or_default()
+}
@@ -0,0 +1,587 @@
+//! MCP client configuration snippets
+//!
+//! Generates the server entry that launches this proxy for popular MCP clients
+//! and merges it into their configuration files (`print-config` / `install`).
+//! Only the entry with the chosen name is ever added or replaced; all other
+//! content of an existing file is preserved.
+
+use crate::config::{AuthMode, Config, ParMode};
+use crate::error::{ProxyError, Result};
+use clap::ValueEnum;
+use serde_json::{json, Map, Value};
+use std::path::{Path, PathBuf};
+
+/// Options holding secrets, which are not written to client configurations
+const SECRET_VARS: &[&str] = &[
+    "OIDC_CLIENT_SECRET",
+    "MCP_PROXY_CREDENTIAL",
+    "MCP_PROXY_PROXY_PASSWORD",
+];
+
+/// Supported MCP clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum McpClient {
+    ClaudeDesktop,
+    Cursor,
+    Windsurf,
+    #[value(name = "vscode")]
+    VsCode,
+}
+
+impl McpClient {
+    /// Top-level key holding the server entries
+    fn servers_key(self) -> &'static str {
+        match self {
+            McpClient::VsCode => "servers",
+            _ => "mcpServers",
+        }
+    }
+
+    /// Default configuration file of the client
+    ///
+    /// VS Code uses the workspace configuration in the current directory.
+    pub fn default_config_path(self) -> Result<PathBuf> {
+        let home = || {
+            std::env::var("HOME")
+                .or_else(|_| std::env::var("USERPROFILE"))
+                .map(PathBuf::from)
+                .map_err(|_| ProxyError::Config("Cannot determine home directory".to_string()))
+        };
+
+        Ok(match self {
+            McpClient::ClaudeDesktop => {
+                let dir = if cfg!(target_os = "macos") {
+                    home()?.join("Library").join("Application Support")
+                } else if cfg!(windows) {
+                    std::env::var("APPDATA").map(PathBuf::from).map_err(|_| {
+                        ProxyError::Config("Cannot determine APPDATA directory".to_string())
+                    })?
+                } else {
+                    home()?.join(".config")
+                };
+                dir.join("Claude").join("claude_desktop_config.json")
+            }
+            McpClient::Cursor => home()?.join(".cursor").join("mcp.json"),
+            McpClient::Windsurf => home()?
+                .join(".codeium")
+                .join("windsurf")
+                .join("mcp_config.json"),
+            McpClient::VsCode => PathBuf::from(".vscode").join("mcp.json"),
+        })
+    }
+}
+
+/// Default entry name: the selected server, else the backend host
+pub fn default_entry_name(config: &Config) -> String {
+    if let Some(ref server) = config.server {
+        return server.clone();
+    }
+
+    url::Url::parse(&config.backend_url)
+        .ok()
+        .and_then(|url| url.host_str().map(String::from))
+        .unwrap_or_else(|| "authful-mcp-proxy".to_string())
+}
+
+/// Build the server entry that launches the proxy with the given configuration
+///
+/// Options are passed as environment variables, with paths made absolute
+/// (the MCP client may launch the proxy from any directory). With `--server`
+/// only the options that differ from the configuration file are passed, so
+/// later edits of the file still take effect. Secrets are left out (see
+/// [`SECRET_VARS`]): client configuration files are usually world-readable.
+pub fn server_entry(client: McpClient, config: &Config, command: &str) -> Result<Value> {
+    let mut args = Vec::new();
+    let mut env = env_vars(config)?;
+
+    if let Some(ref server) = config.server {
+        // Settings come from the configuration file
+        args.push("--server".to_string());
+        args.push(server.clone());
+        if let Some(ref path) = config.config_file {
+            args.push("--config".to_string());
+            args.push(absolute_path(path)?);
+        }
+
+        let mut from_file = Config {
+            server: config.server.clone(),
+            config_file: config.config_file.clone(),
+            ..Default::default()
+        };
+        from_file.apply_config_file()?;
+        for (name, value) in env_vars(&from_file)? {
+            if env.get(&name) == Some(&value) {
+                env.remove(&name);
+            }
+        }
+    } else {
+        args.push(config.backend_url.clone());
+    }
+
+    let omitted: Vec<&str> = SECRET_VARS
+        .iter()
+        .copied()
+        .filter(|name| env.remove(*name).is_some())
+        .collect();
+    if !omitted.is_empty() {
+        tracing::warn!(
+            "Not writing {} to the client configuration. Set them in the environment the MCP client starts the proxy with, use --credential-file or --credential-command for the backend credential, or keep the client secret in the configuration file and use --server",
+            omitted.join(", ")
+        );
+    }
+
+    if config.debug {
+        args.insert(0, "--debug".to_string());
+    }
+    if config.silent {
+        args.insert(0, "--silent".to_string());
+    }
+    if config.log_to_file {
+        args.insert(0, "--log-to-file".to_string());
+    }
+
+    let mut entry = Map::new();
+    if client == McpClient::VsCode {
+        entry.insert("type".to_string(), json!("stdio"));
+    }
+    entry.insert("command".to_string(), json!(command));
+    entry.insert("args".to_string(), json!(args));
+    if !env.is_empty() {
+        entry.insert("env".to_string(), Value::Object(env));
+    }
+
+    Ok(Value::Object(entry))
+}
+
+/// Environment variables reproducing the options of a configuration
+fn env_vars(config: &Config) -> Result<Map<String, Value>> {
+    let mut env = Map::new();
+    let mut set = |name: &str, value: Option<String>| {
+        if let Some(value) = value {
+            env.insert(name.to_string(), Value::String(value));
+        }
+    };
+    let flag = |enabled: bool| enabled.then(|| "true".to_string());
+    let path = |path: &Option<PathBuf>| path.as_deref().map(absolute_path).transpose();
+    let list =
+        |values: &[String], separator: &str| (!values.is_empty()).then(|| values.join(separator));
+
+    if config.auth == AuthMode::Oidc {
+        set("OIDC_ISSUER_URL", Some(config.oidc_issuer_url.clone()));
+        set("OIDC_CLIENT_ID", Some(config.oidc_client_id.clone()));
+        set("OIDC_CLIENT_SECRET", config.oidc_client_secret.clone());
+        set("OIDC_SCOPES", config.oidc_scopes.clone());
+        set("OIDC_REDIRECT_URL", config.oidc_redirect_url.clone());
+        set("OIDC_CALLBACK_PORTS", config.oidc_callback_ports.clone());
+        set(
+            "OIDC_PAR",
+            (config.oidc_par != ParMode::default()).then(|| {
+                config
+                    .oidc_par
+                    .to_possible_value()
+                    .map(|value| value.get_name().to_string())
+                    .unwrap_or_default()
+            }),
+        );
+        set("OIDC_DPOP", flag(config.dpop));
+        set("OIDC_TLS_CLIENT_AUTH", flag(config.oidc_tls_client_auth));
+        set(
+            "OIDC_TOKEN_EXCHANGE_AUDIENCE",
+            config.token_exchange_audience.clone(),
+        );
+        set(
+            "OIDC_TOKEN_EXCHANGE_RESOURCE",
+            config.token_exchange_resource.clone(),
+        );
+        set(
+            "OIDC_TOKEN_EXCHANGE_SCOPES",
+            config.token_exchange_scopes.clone(),
+        );
+        set("OIDC_PROMPT", config.oidc_prompt.clone());
+        set("OIDC_LOGIN_HINT", config.oidc_login_hint.clone());
+        set("OIDC_ACR_VALUES", config.oidc_acr_values.clone());
+        set(
+            "OIDC_MAX_AGE",
+            config.oidc_max_age.map(|max_age| max_age.to_string()),
+        );
+        set("OIDC_UI_LOCALES", config.oidc_ui_locales.clone());
+        set(
+            "OIDC_EXTRA_AUTH_PARAMS",
+            list(&config.extra_auth_params, "\n"),
+        );
+        set("MCP_PROXY_NO_BROWSER", flag(config.no_browser));
+        set(
+            "MCP_PROXY_SUCCESS_TEMPLATE",
+            path(&config.callback_success_template)?,
+        );
+        set(
+            "MCP_PROXY_ERROR_TEMPLATE",
+            path(&config.callback_error_template)?,
+        );
+        set(
+            "MCP_PROXY_SUCCESS_REDIRECT",
+            config.callback_success_redirect.clone(),
+        );
+        set("MCP_PROXY_AUTO_CLOSE", flag(config.callback_auto_close));
+        set("MCP_PROXY_PROFILE", config.profile.clone());
+    } else {
+        set("MCP_PROXY_AUTH", Some(config.auth.name().to_string()));
+        set("MCP_PROXY_CREDENTIAL", config.credential.clone());
+        set("MCP_PROXY_CREDENTIAL_FILE", path(&config.credential_file)?);
+        set(
+            "MCP_PROXY_CREDENTIAL_COMMAND",
+            config.credential_command.clone(),
+        );
+        set(
+            "MCP_PROXY_CREDENTIAL_COMMAND_TIMEOUT",
+            config
+                .credential_command_timeout
+                .map(|timeout| timeout.to_string()),
+        );
+        set("MCP_PROXY_API_KEY_HEADER", config.api_key_header.clone());
+        set("MCP_PROXY_BASIC_USER", config.basic_user.clone());
+    }
+    set("MCP_PROXY_HEADERS", list(&config.headers, "\n"));
+    set(
+        "MCP_PROXY_RETRIES",
+        config.retries.map(|retries| retries.to_string()),
+    );
+    set(
+        "MCP_PROXY_RETRY_BASE_DELAY_MS",
+        config.retry_base_delay_ms.map(|delay| delay.to_string()),
+    );
+    set("MCP_PROXY_RETRY_TOOLS", list(&config.retry_tools, ","));
+    set(
+        "MCP_PROXY_REQUEST_TIMEOUT",
+        config.request_timeout.map(|timeout| timeout.to_string()),
+    );
+    set(
+        "MCP_PROXY_METHOD_TIMEOUTS",
+        list(&config.method_timeouts, ","),
+    );
+    if config.sigv4 {
+        set("MCP_PROXY_SIGV4", flag(true));
+        set("AWS_REGION", config.aws_region.clone());
+        set("MCP_PROXY_AWS_SERVICE", config.aws_service.clone());
+        set("AWS_PROFILE", config.aws_profile.clone());
+        set(
+            "MCP_PROXY_SIGV4_TOKEN_HEADER",
+            config.sigv4_token_header.clone(),
+        );
+    }
+    set("MCP_PROXY_TLS_CLIENT_CERT", path(&config.tls_client_cert)?);
+    set("MCP_PROXY_TLS_CLIENT_KEY", path(&config.tls_client_key)?);
+    set("MCP_PROXY_TLS_CA_BUNDLE", path(&config.tls_ca_bundle)?);
+    set("MCP_PROXY_TLS_EXTRA_ROOTS", path(&config.tls_extra_roots)?);
+    set("MCP_PROXY_HTTP_PROXY", config.http_proxy.clone());
+    set("MCP_PROXY_IDP_PROXY", config.idp_proxy.clone());
+    set("MCP_PROXY_BACKEND_PROXY", config.backend_proxy.clone());
+    set("MCP_PROXY_PROXY_USER", config.proxy_user.clone());
+    set("MCP_PROXY_PROXY_PASSWORD", config.proxy_password.clone());
+    set("MCP_PROXY_NO_PROXY", config.no_proxy.clone());
+    set(
+        "MCP_PROXY_DUMP_MESSAGES",
+        config
+            .dump_messages
+            .as_deref()
+            .map(std::path::absolute)
+            .transpose()?
+            .map(|path| path.display().to_string()),
+    );
+
+    Ok(env)
+}
+
+/// Absolute form of a path given relative to the current directory
+fn absolute_path(path: &Path) -> Result<String> {
+    std::fs::canonicalize(path)
+        .map(|path| path.display().to_string())
+        .map_err(|e| ProxyError::Config(format!("Cannot resolve {}: {}", path.display(), e)))
+}
+
+/// Wrap a server entry in the client's configuration file format
+pub fn snippet(client: McpClient, name: &str, entry: Value) -> Value {
+    json!({ client.servers_key(): { name: entry } })
+}
+
+/// Insert (or replace) a server entry in an existing configuration document
+///
+/// Returns `true` if an entry with the same name was replaced.
+pub fn merge_entry(
+    client: McpClient,
+    document: &mut Value,
+    name: &str,
+    entry: Value,
+) -> Result<bool> {
+    let root = document.as_object_mut().ok_or_else(|| {
+        ProxyError::Config("Client configuration is not a JSON object".to_string())
+    })?;
+
+    let servers = root
+        .entry(client.servers_key())
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .ok_or_else(|| {
+            ProxyError::Config(format!(
+                "\"{}\" in client configuration is not a JSON object",
+                client.servers_key()
+            ))
+        })?;
+
+    Ok(servers.insert(name.to_string(), entry).is_some())
+}
+
+/// Merge a server entry into a client configuration file, creating it if needed
+///
+/// An existing file is backed up to `<file>.bak` before being rewritten.
+pub fn install(client: McpClient, path: &Path, name: &str, entry: Value) -> Result<bool> {
+    let mut document = if path.exists() {
+        let contents = std::fs::read_to_string(path)?;
+        if contents.trim().is_empty() {
+            json!({})
+        } else {
+            serde_json::from_str(&contents).map_err(|e| {
+                ProxyError::Config(format!(
+                    "{} is not valid JSON ({}); refusing to overwrite it",
+                    path.display(),
+                    e
+                ))
+            })?
+        }
+    } else {
+        json!({})
+    };
+
+    let replaced = merge_entry(client, &mut document, name, entry)?;
+
+    if path.exists() {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(".bak");
+        std::fs::copy(path, &backup)?;
+    } else if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    std::fs::write(path, serde_json::to_string_pretty(&document)? + "\n")?;
+    Ok(replaced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            backend_url: "https://mcp.example.com/mcp".to_string(),
+            oidc_issuer_url: "https://auth.example.com".to_string(),
+            oidc_client_id: "client-id".to_string(),
+            oidc_scopes: Some("openid mcp:read".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_claude_desktop_snippet() {
+        let config = config();
+        let entry = server_entry(
+            McpClient::ClaudeDesktop,
+            &config,
+            r"C:\Program Files\authful-mcp-proxy-rs\authful-mcp-proxy-rs.exe",
+        )
+        .unwrap();
+        let snippet = snippet(McpClient::ClaudeDesktop, "tools", entry);
+
+        let server = &snippet["mcpServers"]["tools"];
+        assert_eq!(
+            server["command"],
+            r"C:\Program Files\authful-mcp-proxy-rs\authful-mcp-proxy-rs.exe"
+        );
+        assert_eq!(server["args"], json!(["https://mcp.example.com/mcp"]));
+        assert_eq!(server["env"]["OIDC_ISSUER_URL"], "https://auth.example.com");
+        assert_eq!(server["env"]["OIDC_SCOPES"], "openid mcp:read");
+        assert!(server["env"].get("OIDC_CLIENT_SECRET").is_none());
+        assert!(serde_json::to_string(&snippet)
+            .unwrap()
+            .contains(r"C:\\Program Files"));
+    }
+
+    #[test]
+    fn test_secrets_are_left_out() {
+        let oidc = Config {
+            oidc_client_secret: Some("client-secret".to_string()),
+            http_proxy: Some("http://proxy.example.com:3128".to_string()),
+            proxy_user: Some("proxy-user".to_string()),
+            proxy_password: Some("proxy-password".to_string()),
+            ..config()
+        };
+        let entry = server_entry(McpClient::Cursor, &oidc, "/usr/local/bin/proxy").unwrap();
+        assert!(entry["env"].get("OIDC_CLIENT_SECRET").is_none());
+        assert!(entry["env"].get("MCP_PROXY_PROXY_PASSWORD").is_none());
+        assert_eq!(entry["env"]["MCP_PROXY_PROXY_USER"], "proxy-user");
+
+        let bearer = Config {
+            auth: AuthMode::Bearer,
+            credential: Some("static-token".to_string()),
+            ..config()
+        };
+        let entry = server_entry(McpClient::Cursor, &bearer, "/usr/local/bin/proxy").unwrap();
+        assert_eq!(entry["env"]["MCP_PROXY_AUTH"], "bearer");
+        assert!(entry["env"].get("MCP_PROXY_CREDENTIAL").is_none());
+    }
+
+    #[test]
+    fn test_vscode_snippet_with_server() {
+        let dir = std::env::temp_dir().join(format!("client-vscode-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(
+            &path,
+            r#"
+[servers.tools]
+backend_url = "https://mcp.example.com/mcp"
+issuer_url = "https://auth.example.com"
+client_id = "client-id"
+scopes = "openid mcp:read"
+"#,
+        )
+        .unwrap();
+
+        let mut config = Config {
+            server: Some("tools".to_string()),
+            config_file: Some(path.clone()),
+            ..Default::default()
+        };
+        config.apply_config_file().unwrap();
+        let entry = server_entry(McpClient::VsCode, &config, "/usr/local/bin/proxy").unwrap();
+        let snippet = snippet(McpClient::VsCode, &default_entry_name(&config), entry);
+
+        let server = &snippet["servers"]["tools"];
+        let canonical = std::fs::canonicalize(&path).unwrap();
+        assert_eq!(server["type"], "stdio");
+        assert_eq!(
+            server["args"],
+            json!([
+                "--server",
+                "tools",
+                "--config",
+                canonical.display().to_string()
+            ])
+        );
+        assert!(server.get("env").is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_server_snippet_keeps_overrides() {
+        let dir = std::env::temp_dir().join(format!("client-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(
+            &path,
+            r#"
+[servers.tools]
+backend_url = "https://mcp.example.com/mcp"
+issuer_url = "https://auth.example.com"
+client_id = "client-id"
+prompt = "select_account"
+"#,
+        )
+        .unwrap();
+
+        // Options from the command line, merged over the file as in `main`
+        let mut config = Config {
+            server: Some("tools".to_string()),
+            config_file: Some(path.clone()),
+            no_browser: true,
+            oidc_par: ParMode::Always,
+            dpop: true,
+            oidc_login_hint: Some("alice@example.com".to_string()),
+            extra_auth_params: vec!["domain_hint=example.com".to_string()],
+            no_proxy: Some("localhost".to_string()),
+            tls_ca_bundle: Some(path.clone()),
+            ..Default::default()
+        };
+        config.apply_config_file().unwrap();
+
+        let entry = server_entry(McpClient::Cursor, &config, "proxy").unwrap();
+        let canonical = std::fs::canonicalize(&path).unwrap().display().to_string();
+        assert_eq!(
+            entry["args"],
+            json!(["--server", "tools", "--config", canonical])
+        );
+        assert_eq!(
+            entry["env"],
+            json!({
+                "OIDC_PAR": "always",
+                "OIDC_DPOP": "true",
+                "OIDC_LOGIN_HINT": "alice@example.com",
+                "OIDC_EXTRA_AUTH_PARAMS": "domain_hint=example.com",
+                "MCP_PROXY_NO_BROWSER": "true",
+                "MCP_PROXY_TLS_CA_BUNDLE": canonical,
+                "MCP_PROXY_NO_PROXY": "localhost",
+            })
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_install_preserves_key_order() {
+        let dir = std::env::temp_dir().join(format!("client-install-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("mcp.json");
+        std::fs::write(
+            &path,
+            r#"{"zoom": 1, "mcpServers": {"zeta": {"command": "z"}, "alpha": {"command": "a"}}, "appearance": "dark"}"#,
+        )
+        .unwrap();
+
+        install(
+            McpClient::Cursor,
+            &path,
+            "tools",
+            json!({"command": "proxy"}),
+        )
+        .unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let position = |needle: &str| contents.find(needle).unwrap();
+        assert!(position("\"zoom\"") < position("\"mcpServers\""));
+        assert!(position("\"mcpServers\"") < position("\"appearance\""));
+        assert!(position("\"zeta\"") < position("\"alpha\""));
+        assert!(position("\"alpha\"") < position("\"tools\""));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_merge_preserves_unrelated_entries() {
+        let mut document = json!({
+            "globalShortcut": "Ctrl+Space",
+            "mcpServers": {
+                "other": {"command": "other-server"},
+                "tools": {"command": "old"}
+            }
+        });
+
+        let replaced = merge_entry(
+            McpClient::Cursor,
+            &mut document,
+            "tools",
+            json!({"command": "new"}),
+        )
+        .unwrap();
+
+        assert!(replaced);
+        assert_eq!(document["globalShortcut"], "Ctrl+Space");
+        assert_eq!(document["mcpServers"]["other"]["command"], "other-server");
+        assert_eq!(document["mcpServers"]["tools"]["command"], "new");
+    }
+
+    #[test]
+    fn test_merge_rejects_unexpected_structure() {
+        let mut document = json!({"mcpServers": []});
+        assert!(merge_entry(McpClient::Windsurf, &mut document, "tools", json!({})).is_err());
+    }
+}
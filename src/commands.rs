@@ -0,0 +1,300 @@
+//! One-shot CLI subcommands
+//!
+//! Implements `login`, `status`, `token` and `logout`, which let users
+//! authenticate ahead of time instead of having a browser pop up from inside
+//! the MCP client (`complete-login` finishes one started on a headless host),
+//! and `print-config`/`install` for MCP client onboarding.
+//! The `run` command (the proxy itself) lives in `main.rs`.
+
+use crate::client_config;
+use crate::config::{
+    AuthMode, ClientConfigArgs, CompleteLoginArgs, Config, InstallArgs, LogoutArgs, StatusArgs,
+};
+use crate::error::{ProxyError, Result};
+use crate::http::HttpClientFactory;
+use crate::oidc::exchange::TokenExchange;
+use crate::oidc::userinfo::UserIdentity;
+use crate::oidc::{self, OidcClient, TokenCacheKey, TokenInfo};
+use serde::Serialize;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Cached identity as shown by `status`
+#[derive(Debug, Serialize)]
+struct StatusEntry {
+    profile: Option<String>,
+    user: Option<UserIdentity>,
+    issuer: Option<String>,
+    client_id: Option<String>,
+    backend_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exchanged_for: Option<String>,
+    scopes: Vec<String>,
+    expires_at: Option<u64>,
+    valid: bool,
+    refreshable: bool,
+    file: String,
+}
+
+impl StatusEntry {
+    fn new(path: &std::path::Path, tokens: &TokenInfo) -> Self {
+        let key = tokens.cache_key.as_ref();
+
+        // Prefer the scopes actually granted over the requested ones
+        let scopes = match tokens.scope {
+            Some(ref scope) => scope.split_whitespace().map(String::from).collect(),
+            None => key.map(|k| k.scopes.clone()).unwrap_or_default(),
+        };
+
+        Self {
+            profile: key.and_then(|k| k.profile.clone()),
+            user: tokens.identity(),
+            issuer: key.map(|k| k.issuer.clone()),
+            client_id: key.map(|k| k.client_id.clone()),
+            backend_url: key.and_then(|k| k.resource.clone()),
+            exchanged_for: key.and_then(|k| k.exchanged_for.clone()),
+            scopes,
+            expires_at: tokens.expires_at(),
+            valid: tokens.is_valid(),
+            refreshable: tokens.can_refresh(),
+            file: path.display().to_string(),
+        }
+    }
+}
+
+/// Perform the interactive login flow and cache the resulting tokens
+pub async fn login(config: &Config) -> Result<()> {
+    config.validate()?;
+    require_oidc(config, "login")?;
+
+    let oidc_client = OidcClient::from_config(config).await?;
+    oidc_client.login().await?;
+
+    tracing::info!("Login successful, tokens cached for {}", config.backend_url);
+    Ok(())
+}
+
+/// Token commands only apply to OIDC logins; static credentials are not cached
+fn require_oidc(config: &Config, command: &str) -> Result<()> {
+    if config.auth != AuthMode::Oidc {
+        return Err(ProxyError::Config(format!(
+            "`{}` requires --auth oidc (configured: {})",
+            command,
+            config.auth.name()
+        )));
+    }
+    Ok(())
+}
+
+/// Hand the redirected URL of a headless login to the process waiting for it
+pub async fn complete_login(args: &CompleteLoginArgs) -> Result<()> {
+    let redirected_url = match args.url {
+        Some(ref url) => url.clone(),
+        None => {
+            let mut input = String::new();
+            std::io::stdin().read_line(&mut input)?;
+            input
+        }
+    };
+
+    oidc::callback::deliver_authorization_response(&redirected_url).await?;
+
+    tracing::info!("Authorization response delivered, the waiting login completes the sign-in");
+    Ok(())
+}
+
+/// Print a valid access token to stdout (refreshing or logging in if needed)
+pub async fn token(config: &Config) -> Result<()> {
+    config.validate()?;
+    require_oidc(config, "token")?;
+
+    let oidc_client = OidcClient::from_config(config).await?;
+    let access_token = oidc_client.get_token().await?;
+
+    let mut stdout = std::io::stdout();
+    writeln!(stdout, "{}", access_token)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Show all cached identities
+pub fn status(args: &StatusArgs) -> Result<()> {
+    let entries: Vec<StatusEntry> = TokenInfo::list_cached()?
+        .iter()
+        .map(|(path, tokens)| StatusEntry::new(path, tokens))
+        .collect();
+
+    let mut stdout = std::io::stdout();
+
+    if args.json {
+        writeln!(stdout, "{}", serde_json::to_string_pretty(&entries)?)?;
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        writeln!(stdout, "No cached identities. Run `login` to sign in.")?;
+        return Ok(());
+    }
+
+    for (i, entry) in entries.iter().enumerate() {
+        if i > 0 {
+            writeln!(stdout)?;
+        }
+
+        let unknown = "(unknown - legacy token file)";
+        writeln!(
+            stdout,
+            "Profile:     {}",
+            entry.profile.as_deref().unwrap_or("default")
+        )?;
+        if let Some(ref user) = entry.user {
+            writeln!(stdout, "User:        {}", user)?;
+        }
+        writeln!(
+            stdout,
+            "Issuer:      {}",
+            entry.issuer.as_deref().unwrap_or(unknown)
+        )?;
+        writeln!(
+            stdout,
+            "Client ID:   {}",
+            entry.client_id.as_deref().unwrap_or(unknown)
+        )?;
+        if let Some(ref backend_url) = entry.backend_url {
+            writeln!(stdout, "Backend:     {}", backend_url)?;
+        }
+        if let Some(ref audience) = entry.exchanged_for {
+            writeln!(stdout, "Exchanged:   for {}", audience)?;
+        }
+        writeln!(stdout, "Scopes:      {}", entry.scopes.join(" "))?;
+        writeln!(stdout, "Expires:     {}", format_expiry(entry))?;
+        writeln!(
+            stdout,
+            "Refreshable: {}",
+            if entry.refreshable { "yes" } else { "no" }
+        )?;
+        writeln!(stdout, "File:        {}", entry.file)?;
+    }
+
+    Ok(())
+}
+
+/// Revoke and remove cached tokens for the configured backend (or all of them)
+pub async fn logout(args: &LogoutArgs) -> Result<()> {
+    let config = &args.config;
+
+    if args.all {
+        return oidc::logout::logout_all(
+            &HttpClientFactory::from_config(config)?,
+            &config.oidc_client_id,
+            config.client_secret(),
+            args.end_session,
+        )
+        .await;
+    }
+
+    config.validate()?;
+    require_oidc(config, "logout")?;
+
+    let http = HttpClientFactory::from_config(config)?;
+    let key = TokenCacheKey::from_config(config);
+
+    // Exchanged tokens depend on the login tokens and go with them
+    if let Some(exchange) = TokenExchange::from_config(config) {
+        oidc::logout::logout(
+            &http,
+            &exchange.cache_key(&key),
+            config.client_secret(),
+            false,
+        )
+        .await?;
+    }
+
+    oidc::logout::logout(&http, &key, config.client_secret(), args.end_session).await
+}
+
+/// Print the MCP client configuration snippet for this proxy
+pub fn print_config(args: &ClientConfigArgs) -> Result<()> {
+    let (name, entry) = client_entry(args)?;
+    let snippet = client_config::snippet(args.client, &name, entry);
+
+    let mut stdout = std::io::stdout();
+    writeln!(stdout, "{}", serde_json::to_string_pretty(&snippet)?)?;
+    Ok(())
+}
+
+/// Merge this proxy into an MCP client's configuration file
+pub fn install(args: &InstallArgs) -> Result<()> {
+    let (name, entry) = client_entry(&args.client)?;
+    let path = match args.file {
+        Some(ref path) => path.clone(),
+        None => args.client.client.default_config_path()?,
+    };
+
+    let replaced = client_config::install(args.client.client, &path, &name, entry)?;
+
+    tracing::info!(
+        "{} server '{}' in {}",
+        if replaced { "Updated" } else { "Added" },
+        name,
+        path.display()
+    );
+    tracing::info!("Restart your MCP client to apply the change");
+    Ok(())
+}
+
+fn client_entry(args: &ClientConfigArgs) -> Result<(String, serde_json::Value)> {
+    args.config.validate()?;
+
+    let command = std::env::current_exe()?.display().to_string();
+    let name = args
+        .name
+        .clone()
+        .unwrap_or_else(|| client_config::default_entry_name(&args.config));
+    let entry = client_config::server_entry(args.client, &args.config, &command)?;
+
+    Ok((name, entry))
+}
+
+fn format_expiry(entry: &StatusEntry) -> String {
+    let Some(expires_at) = entry.expires_at else {
+        return "unknown".to_string();
+    };
+
+    let timestamp = chrono::DateTime::from_timestamp(expires_at as i64, 0)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| expires_at.to_string());
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    if entry.valid {
+        format!("{} (in {})", timestamp, format_duration(expires_at - now))
+    } else {
+        format!("{} (expired)", timestamp)
+    }
+}
+
+fn format_duration(secs: u64) -> String {
+    match secs {
+        s if s >= 86_400 => format!("{}d {}h", s / 86_400, (s % 86_400) / 3_600),
+        s if s >= 3_600 => format!("{}h {}m", s / 3_600, (s % 3_600) / 60),
+        s if s >= 60 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(45), "45s");
+        assert_eq!(format_duration(300), "5m");
+        assert_eq!(format_duration(3_900), "1h 5m");
+        assert_eq!(format_duration(90_000), "1d 1h");
+    }
+}
@@ -1,29 +1,231 @@
 //! Configuration parsing and validation
 
+use crate::client_config::McpClient;
+use crate::config_file::ConfigFile;
 use crate::error::{ProxyError, Result};
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
 
 const DEFAULT_SCOPES: &str = "openid profile email";
 const DEFAULT_REDIRECT_URL: &str = "http://localhost:8080/auth/callback";
 
-#[derive(Parser, Debug, Clone)]
+/// Authorization request parameters set by the proxy itself
+const RESERVED_AUTH_PARAMS: &[&str] = &[
+    "response_type",
+    "client_id",
+    "redirect_uri",
+    "scope",
+    "state",
+    "code_challenge",
+    "code_challenge_method",
+    "dpop_jkt",
+    "request_uri",
+    "request",
+    "response_mode",
+    "nonce",
+];
+
+/// Command line interface
+///
+/// Without a subcommand the proxy runs with the top-level options (same as `run`).
+#[derive(Parser, Debug)]
 #[command(
     name = "authful-mcp-proxy-rs",
     version,
     about = "Authful Remote-HTTP-to-Local-stdio MCP Proxy",
-    long_about = "MCP proxy that bridges remote HTTP MCP servers with OIDC authentication to local stdio transport for MCP clients like Claude Desktop"
+    long_about = "MCP proxy that bridges remote HTTP MCP servers with OIDC authentication to local stdio transport for MCP clients like Claude Desktop",
+    args_conflicts_with_subcommands = true
 )]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub config: Config,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run the proxy (default when no subcommand is given)
+    Run(Config),
+
+    /// Perform the interactive login flow and exit
+    Login(Config),
+
+    /// Show cached identities (issuer, client, scopes, expiry) per profile
+    Status(StatusArgs),
+
+    /// Print a valid access token to stdout, refreshing it if needed
+    Token(Config),
+
+    /// Revoke cached tokens and remove them from the token cache
+    Logout(LogoutArgs),
+
+    /// Diagnose configuration, OIDC provider, token cache and backend connectivity
+    Doctor(Config),
+
+    /// Complete a login waiting on this machine with the URL the browser was redirected to
+    CompleteLogin(CompleteLoginArgs),
+
+    /// Print an MCP client configuration snippet that launches this proxy
+    PrintConfig(ClientConfigArgs),
+
+    /// Add (or update) this proxy in an MCP client's configuration file
+    Install(InstallArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct CompleteLoginArgs {
+    /// Redirected URL copied from the browser's address bar (read from stdin if omitted)
+    #[arg(long, value_name = "URL")]
+    pub url: Option<String>,
+
+    #[command(flatten)]
+    pub config: Config,
+}
+
+#[derive(Args, Debug)]
+pub struct ClientConfigArgs {
+    /// MCP client to generate the configuration for
+    #[arg(long, value_enum)]
+    pub client: McpClient,
+
+    /// Name of the server entry (default: the --server name or the backend host)
+    #[arg(long)]
+    pub name: Option<String>,
+
+    #[command(flatten)]
+    pub config: Config,
+}
+
+#[derive(Args, Debug)]
+pub struct InstallArgs {
+    /// Client configuration file to update (default: the client's standard location)
+    #[arg(long, value_name = "PATH")]
+    pub file: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub client: ClientConfigArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct StatusArgs {
+    /// Print status as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    #[command(flatten)]
+    pub config: Config,
+}
+
+#[derive(Args, Debug)]
+pub struct LogoutArgs {
+    /// Sign out of every cached identity (all issuers and profiles)
+    #[arg(long)]
+    pub all: bool,
+
+    /// Also end the session at the provider (opens the end_session_endpoint in the browser)
+    #[arg(long)]
+    pub end_session: bool,
+
+    #[command(flatten)]
+    pub config: Config,
+}
+
+/// How backend requests are authenticated
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AuthMode {
+    /// OIDC login with the authorization code flow
+    #[default]
+    Oidc,
+    /// Static bearer token
+    Bearer,
+    /// API key in a custom header
+    ApiKey,
+    /// HTTP Basic authentication
+    Basic,
+    /// Bearer token printed as JSON by an external command
+    Command,
+    /// No credentials (e.g. with --sigv4 only)
+    None,
+}
+
+impl AuthMode {
+    /// Name as accepted by `--auth`
+    pub fn name(self) -> &'static str {
+        match self {
+            AuthMode::Oidc => "oidc",
+            AuthMode::Bearer => "bearer",
+            AuthMode::ApiKey => "api-key",
+            AuthMode::Basic => "basic",
+            AuthMode::Command => "command",
+            AuthMode::None => "none",
+        }
+    }
+}
+
+/// When to use pushed authorization requests
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParMode {
+    /// Use PAR when the provider advertises a PAR endpoint
+    #[default]
+    Auto,
+    /// Always use PAR, fail if the provider does not support it
+    Always,
+    /// Never use PAR
+    Never,
+}
+
+impl Cli {
+    /// Parse command line arguments and environment variables
+    pub fn parse_args() -> Self {
+        Cli::parse()
+    }
+
+    /// Configuration of the selected command
+    pub fn config_mut(&mut self) -> &mut Config {
+        match self.command {
+            None => &mut self.config,
+            Some(Command::Run(ref mut config))
+            | Some(Command::Login(ref mut config))
+            | Some(Command::Token(ref mut config))
+            | Some(Command::Doctor(ref mut config)) => config,
+            Some(Command::Status(ref mut args)) => &mut args.config,
+            Some(Command::Logout(ref mut args)) => &mut args.config,
+            Some(Command::CompleteLogin(ref mut args)) => &mut args.config,
+            Some(Command::PrintConfig(ref mut args)) => &mut args.config,
+            Some(Command::Install(ref mut args)) => &mut args.client.config,
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone, Default)]
 pub struct Config {
     /// URL of remote backend MCP server to be proxied
-    #[arg(value_name = "MCP_BACKEND_URL", env = "MCP_BACKEND_URL")]
+    #[arg(
+        value_name = "MCP_BACKEND_URL",
+        env = "MCP_BACKEND_URL",
+        default_value = "",
+        hide_default_value = true
+    )]
     pub backend_url: String,
 
     /// OIDC issuer URL (e.g., https://auth.example.com)
-    #[arg(long, env = "OIDC_ISSUER_URL")]
+    #[arg(
+        long,
+        env = "OIDC_ISSUER_URL",
+        default_value = "",
+        hide_default_value = true
+    )]
     pub oidc_issuer_url: String,
 
     /// OAuth client ID
-    #[arg(long, env = "OIDC_CLIENT_ID")]
+    #[arg(
+        long,
+        env = "OIDC_CLIENT_ID",
+        default_value = "",
+        hide_default_value = true
+    )]
     pub oidc_client_id: String,
 
     /// OAuth client secret (optional for public clients)
@@ -38,6 +240,244 @@ pub struct Config {
     #[arg(long, env = "OIDC_REDIRECT_URL")]
     pub oidc_redirect_url: Option<String>,
 
+    /// Callback ports to try in order instead of the redirect URL's port (e.g. "8080-8090")
+    #[arg(long, env = "OIDC_CALLBACK_PORTS")]
+    pub oidc_callback_ports: Option<String>,
+
+    /// Pushed authorization requests (RFC 9126): use when the provider supports them,
+    /// always (fail if unsupported) or never
+    #[arg(long, env = "OIDC_PAR", value_enum, default_value_t = ParMode::Auto)]
+    pub oidc_par: ParMode,
+
+    /// Bind tokens to a per-profile key with DPoP proofs (RFC 9449)
+    #[arg(long, env = "OIDC_DPOP")]
+    pub dpop: bool,
+
+    /// Authenticate the client to the provider with --tls-client-cert
+    /// (RFC 8705 tls_client_auth) instead of the client secret
+    #[arg(long, env = "OIDC_TLS_CLIENT_AUTH")]
+    pub oidc_tls_client_auth: bool,
+
+    /// Exchange the login token for a token for this audience (RFC 8693 token exchange)
+    #[arg(long, value_name = "AUDIENCE", env = "OIDC_TOKEN_EXCHANGE_AUDIENCE")]
+    pub token_exchange_audience: Option<String>,
+
+    /// Exchange the login token for a token for this resource URI (RFC 8693 token exchange)
+    #[arg(long, value_name = "URI", env = "OIDC_TOKEN_EXCHANGE_RESOURCE")]
+    pub token_exchange_resource: Option<String>,
+
+    /// Space-separated scopes to request for the exchanged token
+    #[arg(long, value_name = "SCOPES", env = "OIDC_TOKEN_EXCHANGE_SCOPES")]
+    pub token_exchange_scopes: Option<String>,
+
+    /// How to authenticate backend requests: OIDC login, a static bearer token,
+    /// an API key header, HTTP Basic, a token command or none
+    #[arg(long, env = "MCP_PROXY_AUTH", value_enum, default_value_t = AuthMode::Oidc)]
+    pub auth: AuthMode,
+
+    /// Credential for the bearer, api-key and basic modes (the password for basic)
+    #[arg(long, value_name = "SECRET", env = "MCP_PROXY_CREDENTIAL")]
+    pub credential: Option<String>,
+
+    /// Read the credential from this file
+    #[arg(long, value_name = "PATH", env = "MCP_PROXY_CREDENTIAL_FILE")]
+    pub credential_file: Option<PathBuf>,
+
+    /// Run this shell command and use its output as the credential
+    /// (with --auth command: JSON with `access_token` and `expires_at`)
+    #[arg(long, value_name = "COMMAND", env = "MCP_PROXY_CREDENTIAL_COMMAND")]
+    pub credential_command: Option<String>,
+
+    /// Seconds to wait for the credential command (default: 120)
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        env = "MCP_PROXY_CREDENTIAL_COMMAND_TIMEOUT"
+    )]
+    pub credential_command_timeout: Option<u64>,
+
+    /// Header carrying the API key (default: X-API-Key)
+    #[arg(long, value_name = "NAME", env = "MCP_PROXY_API_KEY_HEADER")]
+    pub api_key_header: Option<String>,
+
+    /// User name for HTTP Basic authentication
+    #[arg(long, value_name = "USER", env = "MCP_PROXY_BASIC_USER")]
+    pub basic_user: Option<String>,
+
+    /// Header for every backend request (repeatable, e.g. 'X-Tenant: ${TENANT}'
+    /// or 'X-User: {{claims.email}}'); never sent to the OIDC provider
+    #[arg(
+        long = "header",
+        value_name = "NAME: VALUE",
+        env = "MCP_PROXY_HEADERS",
+        value_delimiter = '\n'
+    )]
+    pub headers: Vec<String>,
+
+    /// Retries of idempotent requests after network errors and 429/502/503/504
+    /// responses (default: 2, 0 disables retries)
+    #[arg(long, value_name = "COUNT", env = "MCP_PROXY_RETRIES")]
+    pub retries: Option<u32>,
+
+    /// Delay before the first retry in milliseconds, doubled for each further retry
+    /// (default: 250)
+    #[arg(long, value_name = "MS", env = "MCP_PROXY_RETRY_BASE_DELAY_MS")]
+    pub retry_base_delay_ms: Option<u64>,
+
+    /// Tool whose `tools/call` requests may be retried (repeatable; tool calls
+    /// are never retried otherwise)
+    #[arg(
+        long = "retry-tool",
+        value_name = "NAME",
+        env = "MCP_PROXY_RETRY_TOOLS",
+        value_delimiter = ','
+    )]
+    pub retry_tools: Vec<String>,
+
+    /// Timeout in seconds for backend requests; a timed-out request gets a
+    /// JSON-RPC error and is cancelled at the backend (default: none)
+    #[arg(long, value_name = "SECONDS", env = "MCP_PROXY_REQUEST_TIMEOUT")]
+    pub request_timeout: Option<u64>,
+
+    /// Timeout for one method, overriding --request-timeout (repeatable,
+    /// e.g. tools/call=600)
+    #[arg(
+        long = "method-timeout",
+        value_name = "METHOD=SECONDS",
+        env = "MCP_PROXY_METHOD_TIMEOUTS",
+        value_delimiter = ','
+    )]
+    pub method_timeouts: Vec<String>,
+
+    /// Sign backend requests with AWS Signature Version 4 (e.g. IAM-protected
+    /// API Gateway or Lambda function URLs)
+    #[arg(long, env = "MCP_PROXY_SIGV4")]
+    pub sigv4: bool,
+
+    /// AWS region for SigV4 signing (default: AWS_REGION / AWS_DEFAULT_REGION)
+    #[arg(long, value_name = "REGION")]
+    pub aws_region: Option<String>,
+
+    /// AWS service name for SigV4 signing (default: execute-api; "lambda" for function URLs)
+    #[arg(long, value_name = "SERVICE", env = "MCP_PROXY_AWS_SERVICE")]
+    pub aws_service: Option<String>,
+
+    /// Profile in the AWS credentials file (used without AWS_ACCESS_KEY_ID)
+    #[arg(long, value_name = "PROFILE", env = "AWS_PROFILE")]
+    pub aws_profile: Option<String>,
+
+    /// Header for the token of --auth when signing with SigV4, which takes
+    /// over the Authorization header (default: X-Authorization)
+    #[arg(long, value_name = "NAME", env = "MCP_PROXY_SIGV4_TOKEN_HEADER")]
+    pub sigv4_token_header: Option<String>,
+
+    /// PEM client certificate (chain) for mutual TLS with the provider and backend
+    #[arg(long, value_name = "PATH", env = "MCP_PROXY_TLS_CLIENT_CERT")]
+    pub tls_client_cert: Option<PathBuf>,
+
+    /// PEM private key of the client certificate (if not contained in the certificate file)
+    #[arg(long, value_name = "PATH", env = "MCP_PROXY_TLS_CLIENT_KEY")]
+    pub tls_client_key: Option<PathBuf>,
+
+    /// PEM CA bundle to trust instead of the built-in root certificates
+    #[arg(long, value_name = "PATH", env = "MCP_PROXY_TLS_CA_BUNDLE")]
+    pub tls_ca_bundle: Option<PathBuf>,
+
+    /// PEM certificates to trust in addition to the built-in root certificates
+    #[arg(long, value_name = "PATH", env = "MCP_PROXY_TLS_EXTRA_ROOTS")]
+    pub tls_extra_roots: Option<PathBuf>,
+
+    /// Outbound HTTP proxy URL for all requests (`direct` disables proxying;
+    /// defaults to the HTTP_PROXY/HTTPS_PROXY environment variables)
+    #[arg(long, value_name = "URL", env = "MCP_PROXY_HTTP_PROXY")]
+    pub http_proxy: Option<String>,
+
+    /// Outbound proxy for OIDC provider requests, overriding --http-proxy
+    #[arg(long, value_name = "URL", env = "MCP_PROXY_IDP_PROXY")]
+    pub idp_proxy: Option<String>,
+
+    /// Outbound proxy for MCP backend requests, overriding --http-proxy
+    #[arg(long, value_name = "URL", env = "MCP_PROXY_BACKEND_PROXY")]
+    pub backend_proxy: Option<String>,
+
+    /// Username for the outbound proxy (alternatively part of the proxy URL)
+    #[arg(long, value_name = "USER", env = "MCP_PROXY_PROXY_USER")]
+    pub proxy_user: Option<String>,
+
+    /// Password for the outbound proxy
+    #[arg(long, value_name = "PASSWORD", env = "MCP_PROXY_PROXY_PASSWORD")]
+    pub proxy_password: Option<String>,
+
+    /// Comma-separated hosts, domains and CIDR ranges to reach without the proxy
+    #[arg(long, value_name = "LIST", env = "MCP_PROXY_NO_PROXY")]
+    pub no_proxy: Option<String>,
+
+    /// Do not open a browser; print the authorization URL and accept the redirected URL
+    /// pasted into the terminal or passed to `complete-login` (for headless hosts)
+    #[arg(long, env = "MCP_PROXY_NO_BROWSER")]
+    pub no_browser: bool,
+
+    /// OIDC `prompt` parameter (e.g. "select_account", "login", "consent")
+    #[arg(long, env = "OIDC_PROMPT")]
+    pub oidc_prompt: Option<String>,
+
+    /// OIDC `login_hint` parameter, pre-filling the user name at the provider
+    #[arg(long, env = "OIDC_LOGIN_HINT")]
+    pub oidc_login_hint: Option<String>,
+
+    /// OIDC `acr_values` parameter, requesting an authentication level (e.g. MFA)
+    #[arg(long, env = "OIDC_ACR_VALUES")]
+    pub oidc_acr_values: Option<String>,
+
+    /// OIDC `max_age` parameter: maximum seconds since the user last authenticated
+    #[arg(long, value_name = "SECONDS", env = "OIDC_MAX_AGE")]
+    pub oidc_max_age: Option<u64>,
+
+    /// OIDC `ui_locales` parameter: preferred languages of the login page (e.g. "de en")
+    #[arg(long, env = "OIDC_UI_LOCALES")]
+    pub oidc_ui_locales: Option<String>,
+
+    /// Additional authorization request parameter (repeatable, e.g. domain_hint=example.com;
+    /// one per line in the environment variable, as values may contain commas)
+    #[arg(
+        long = "auth-param",
+        value_name = "NAME=VALUE",
+        env = "OIDC_EXTRA_AUTH_PARAMS",
+        value_delimiter = '\n'
+    )]
+    pub extra_auth_params: Vec<String>,
+
+    /// HTML template for the page shown after a successful login
+    /// (placeholders: {{issuer}}, {{user}}, {{script}})
+    #[arg(long, value_name = "PATH", env = "MCP_PROXY_SUCCESS_TEMPLATE")]
+    pub callback_success_template: Option<PathBuf>,
+
+    /// HTML template for the page shown after a failed login
+    /// (placeholders: {{issuer}}, {{error}}, {{script}})
+    #[arg(long, value_name = "PATH", env = "MCP_PROXY_ERROR_TEMPLATE")]
+    pub callback_error_template: Option<PathBuf>,
+
+    /// Redirect the browser to this URL after a successful login
+    #[arg(long, value_name = "URL", env = "MCP_PROXY_SUCCESS_REDIRECT")]
+    pub callback_success_redirect: Option<String>,
+
+    /// Try to close the browser tab after a successful login
+    #[arg(long, env = "MCP_PROXY_AUTO_CLOSE")]
+    pub callback_auto_close: bool,
+
+    /// Named profile for keeping separate identities against the same issuer (e.g. "admin")
+    #[arg(long, env = "MCP_PROXY_PROFILE")]
+    pub profile: Option<String>,
+
+    /// Named server from the configuration file to use for unset options
+    #[arg(long, env = "MCP_PROXY_SERVER")]
+    pub server: Option<String>,
+
+    /// Configuration file with named servers and providers
+    /// (default: ~/.config/authful-mcp-proxy/config.toml)
+    #[arg(long = "config", value_name = "PATH", env = "MCP_PROXY_CONFIG")]
+    pub config_file: Option<PathBuf>,
+
     /// Disable all logging (no stderr output)
     #[arg(long, conflicts_with = "debug")]
     pub silent: bool,
@@ -56,9 +496,55 @@ pub struct Config {
 }
 
 impl Config {
-    /// Parse configuration from CLI arguments and environment variables
-    pub fn parse_args() -> Self {
-        Config::parse()
+    /// Fill unset options from the server selected with `--server`
+    ///
+    /// Options given on the command line or via environment variables take
+    /// precedence over the configuration file.
+    pub fn apply_config_file(&mut self) -> Result<()> {
+        let Some(ref server) = self.server else {
+            return Ok(());
+        };
+
+        let path = match self.config_file {
+            Some(ref path) => path.clone(),
+            None => ConfigFile::default_path()?,
+        };
+        let entry = ConfigFile::load(&path)?.resolve_server(server)?;
+
+        fill(&mut self.backend_url, entry.backend_url);
+        fill(&mut self.oidc_issuer_url, entry.issuer_url);
+        fill(&mut self.oidc_client_id, entry.client_id);
+        self.oidc_client_secret = self.oidc_client_secret.take().or(entry.client_secret);
+        self.oidc_scopes = self.oidc_scopes.take().or(entry.scopes);
+        self.oidc_redirect_url = self.oidc_redirect_url.take().or(entry.redirect_url);
+        self.oidc_callback_ports = self.oidc_callback_ports.take().or(entry.callback_ports);
+        self.oidc_prompt = self.oidc_prompt.take().or(entry.prompt);
+        self.oidc_login_hint = self.oidc_login_hint.take().or(entry.login_hint);
+        self.oidc_acr_values = self.oidc_acr_values.take().or(entry.acr_values);
+        self.oidc_max_age = self.oidc_max_age.or(entry.max_age);
+        self.oidc_ui_locales = self.oidc_ui_locales.take().or(entry.ui_locales);
+        for (name, value) in entry.extra_auth_params {
+            let set = self
+                .extra_auth_params
+                .iter()
+                .any(|param| param.split_once('=').is_some_and(|(n, _)| n.trim() == name));
+            if !set {
+                self.extra_auth_params.push(format!("{}={}", name, value));
+            }
+        }
+        for (name, value) in entry.headers {
+            let set = self.headers.iter().any(|header| {
+                header
+                    .split_once(':')
+                    .is_some_and(|(n, _)| n.trim().eq_ignore_ascii_case(&name))
+            });
+            if !set {
+                self.headers.push(format!("{}: {}", name, value));
+            }
+        }
+        self.profile = self.profile.take().or(entry.profile);
+
+        Ok(())
     }
 
     /// Validate the configuration
@@ -69,6 +555,25 @@ impl Config {
             ));
         }
 
+        url::Url::parse(&self.backend_url)
+            .map_err(|e| ProxyError::Config(format!("Invalid backend URL: {}", e)))?;
+
+        crate::headers::from_config(self)?;
+        crate::proxy::timeout::RequestTimeouts::from_config(self)?;
+
+        if self.sigv4 {
+            self.aws_region()?;
+            if let Some(ref header) = self.sigv4_token_header {
+                reqwest::header::HeaderName::from_bytes(header.as_bytes()).map_err(|_| {
+                    ProxyError::Config(format!("Invalid SigV4 token header name '{}'", header))
+                })?;
+            }
+        }
+
+        if self.auth != AuthMode::Oidc {
+            return self.validate_credential();
+        }
+
         if self.oidc_issuer_url.is_empty() {
             return Err(ProxyError::Config(
                 "OIDC issuer URL is required".to_string(),
@@ -79,10 +584,6 @@ impl Config {
             return Err(ProxyError::Config("OIDC client ID is required".to_string()));
         }
 
-        // Validate URLs
-        url::Url::parse(&self.backend_url)
-            .map_err(|e| ProxyError::Config(format!("Invalid backend URL: {}", e)))?;
-
         url::Url::parse(&self.oidc_issuer_url)
             .map_err(|e| ProxyError::Config(format!("Invalid OIDC issuer URL: {}", e)))?;
 
@@ -91,9 +592,132 @@ impl Config {
                 .map_err(|e| ProxyError::Config(format!("Invalid redirect URL: {}", e)))?;
         }
 
+        self.callback_ports()?;
+        self.authorization_params()?;
+
+        if self.oidc_tls_client_auth {
+            if self.tls_client_cert.is_none() {
+                return Err(ProxyError::Config(
+                    "--oidc-tls-client-auth requires --tls-client-cert".to_string(),
+                ));
+            }
+            if self.oidc_client_secret.is_some() {
+                tracing::warn!(
+                    "Not sending the client secret: the client certificate authenticates the client (--oidc-tls-client-auth)"
+                );
+            }
+        }
+
+        if let Some(ref url) = self.callback_success_redirect {
+            let url = url::Url::parse(url).map_err(|e| {
+                ProxyError::Config(format!("Invalid callback success redirect URL: {}", e))
+            })?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(ProxyError::Config(
+                    "Callback success redirect URL must use http or https".to_string(),
+                ));
+            }
+        }
+
+        if let Some(ref resource) = self.token_exchange_resource {
+            url::Url::parse(resource).map_err(|e| {
+                ProxyError::Config(format!("Invalid token exchange resource URI: {}", e))
+            })?;
+        }
+
+        if self.token_exchange_scopes.is_some()
+            && self.token_exchange_audience.is_none()
+            && self.token_exchange_resource.is_none()
+        {
+            return Err(ProxyError::Config(
+                "--token-exchange-scopes requires --token-exchange-audience or --token-exchange-resource"
+                    .to_string(),
+            ));
+        }
+
+        if let Some(ref profile) = self.profile {
+            if profile.is_empty()
+                || !profile
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(ProxyError::Config(format!(
+                    "Invalid profile name '{}': use only letters, digits, '-' and '_'",
+                    profile
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validate the options of the static credential modes
+    fn validate_credential(&self) -> Result<()> {
+        if self.auth == AuthMode::None {
+            return Ok(());
+        }
+
+        if self.auth == AuthMode::Command {
+            if self.credential_command.is_none()
+                || self.credential.is_some()
+                || self.credential_file.is_some()
+            {
+                return Err(ProxyError::Config(
+                    "--auth command requires --credential-command (and no other credential)"
+                        .to_string(),
+                ));
+            }
+            return Ok(());
+        }
+
+        let sources = [
+            self.credential.is_some(),
+            self.credential_file.is_some(),
+            self.credential_command.is_some(),
+        ];
+        match sources.iter().filter(|set| **set).count() {
+            0 => {
+                return Err(ProxyError::Config(format!(
+                    "--auth {} requires --credential, --credential-file or --credential-command",
+                    self.auth.name()
+                )))
+            }
+            1 => {}
+            _ => return Err(ProxyError::Config(
+                "Only one of --credential, --credential-file and --credential-command can be given"
+                    .to_string(),
+            )),
+        }
+
+        if self.auth == AuthMode::Basic && self.basic_user.is_none() {
+            return Err(ProxyError::Config(
+                "--auth basic requires --basic-user".to_string(),
+            ));
+        }
+
+        if let Some(ref header) = self.api_key_header {
+            reqwest::header::HeaderName::from_bytes(header.as_bytes()).map_err(|_| {
+                ProxyError::Config(format!("Invalid API key header name '{}'", header))
+            })?;
+        }
+
         Ok(())
     }
 
+    /// Get the AWS region for SigV4 signing
+    pub fn aws_region(&self) -> Result<String> {
+        self.aws_region
+            .clone()
+            .or_else(|| std::env::var("AWS_REGION").ok())
+            .or_else(|| std::env::var("AWS_DEFAULT_REGION").ok())
+            .filter(|region| !region.is_empty())
+            .ok_or_else(|| {
+                ProxyError::Config(
+                    "--sigv4 requires --aws-region, AWS_REGION or AWS_DEFAULT_REGION".to_string(),
+                )
+            })
+    }
+
     /// Get OAuth scopes as a list (with defaults)
     pub fn scopes(&self) -> Vec<String> {
         let scopes_str = self.oidc_scopes.as_deref().unwrap_or(DEFAULT_SCOPES);
@@ -108,6 +732,14 @@ impl Config {
         scopes
     }
 
+    /// Client secret to send to the provider (none when the client
+    /// certificate authenticates the client)
+    pub fn client_secret(&self) -> Option<&str> {
+        self.oidc_client_secret
+            .as_deref()
+            .filter(|_| !self.oidc_tls_client_auth)
+    }
+
     /// Get redirect URL (with default)
     pub fn redirect_url(&self) -> String {
         self.oidc_redirect_url
@@ -115,6 +747,67 @@ impl Config {
             .unwrap_or_else(|| DEFAULT_REDIRECT_URL.to_string())
     }
 
+    /// Get the callback ports to try, if configured
+    pub fn callback_ports(&self) -> Result<Option<Vec<u16>>> {
+        self.oidc_callback_ports
+            .as_deref()
+            .map(crate::oidc::callback::parse_port_range)
+            .transpose()
+    }
+
+    /// Get the additional authorization request parameters
+    ///
+    /// Parameters the proxy sets itself (PKCE, state, redirect URI etc.) cannot
+    /// be overridden.
+    pub fn authorization_params(&self) -> Result<Vec<(String, String)>> {
+        let mut params: Vec<(String, String)> = [
+            ("prompt", self.oidc_prompt.clone()),
+            ("login_hint", self.oidc_login_hint.clone()),
+            ("acr_values", self.oidc_acr_values.clone()),
+            (
+                "max_age",
+                self.oidc_max_age.map(|max_age| max_age.to_string()),
+            ),
+            ("ui_locales", self.oidc_ui_locales.clone()),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value?)))
+        .collect();
+
+        for param in &self.extra_auth_params {
+            let (name, value) = param.split_once('=').ok_or_else(|| {
+                ProxyError::Config(format!(
+                    "Invalid authorization parameter '{}': expected NAME=VALUE",
+                    param
+                ))
+            })?;
+            let name = name.trim();
+
+            if name.is_empty() {
+                return Err(ProxyError::Config(format!(
+                    "Invalid authorization parameter '{}': empty name",
+                    param
+                )));
+            }
+            if RESERVED_AUTH_PARAMS.contains(&name) {
+                return Err(ProxyError::Config(format!(
+                    "Authorization parameter '{}' is set by the proxy and cannot be overridden",
+                    name
+                )));
+            }
+            if params.iter().any(|(existing, _)| existing == name) {
+                return Err(ProxyError::Config(format!(
+                    "Authorization parameter '{}' is given more than once",
+                    name
+                )));
+            }
+
+            params.push((name.to_string(), value.to_string()));
+        }
+
+        Ok(params)
+    }
+
     /// Get log level based on flags
     pub fn log_level(&self) -> tracing::Level {
         if self.debug {
@@ -144,6 +837,15 @@ impl Config {
     }
 }
 
+/// Set a required option from the config file unless it was already given
+fn fill(value: &mut String, from_file: Option<String>) {
+    if value.is_empty() {
+        if let Some(from_file) = from_file {
+            *value = from_file;
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -161,6 +863,7 @@ mod tests {
             debug: false,
             log_to_file: false,
             dump_messages: None,
+            ..Default::default()
         };
 
         let scopes = config.scopes();
@@ -182,6 +885,7 @@ mod tests {
             debug: false,
             log_to_file: false,
             dump_messages: None,
+            ..Default::default()
         };
 
         let scopes = config.scopes();
@@ -203,8 +907,178 @@ mod tests {
             debug: false,
             log_to_file: false,
             dump_messages: None,
+            ..Default::default()
         };
 
         assert_eq!(config.redirect_url(), DEFAULT_REDIRECT_URL);
     }
+
+    #[test]
+    fn test_apply_config_file_precedence() {
+        let path = std::env::temp_dir().join(format!(
+            "authful-mcp-proxy-test-{}.toml",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            r#"
+            [providers.corp]
+            issuer_url = "https://auth.company.com"
+            client_id = "file-client"
+            scopes = "openid mcp:read"
+
+            [servers.tools]
+            backend_url = "https://mcp.company.com/mcp"
+            provider = "corp"
+            "#,
+        )
+        .unwrap();
+
+        let mut config = Config {
+            oidc_client_id: "cli-client".to_string(),
+            server: Some("tools".to_string()),
+            config_file: Some(path.clone()),
+            ..Default::default()
+        };
+        let result = config.apply_config_file();
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+
+        assert_eq!(config.backend_url, "https://mcp.company.com/mcp");
+        assert_eq!(config.oidc_issuer_url, "https://auth.company.com");
+        assert_eq!(config.oidc_client_id, "cli-client");
+        assert_eq!(config.oidc_scopes.as_deref(), Some("openid mcp:read"));
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_profile_name() {
+        let mut config = Config {
+            backend_url: "https://backend.example.com".to_string(),
+            oidc_issuer_url: "https://auth.example.com".to_string(),
+            oidc_client_id: "client-id".to_string(),
+            profile: Some("admin_2".to_string()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        config.profile = Some("../admin".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_authorization_params() {
+        let mut config = Config {
+            oidc_prompt: Some("select_account".to_string()),
+            oidc_max_age: Some(0),
+            extra_auth_params: vec!["domain_hint=example.com".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            config.authorization_params().unwrap(),
+            vec![
+                ("prompt".to_string(), "select_account".to_string()),
+                ("max_age".to_string(), "0".to_string()),
+                ("domain_hint".to_string(), "example.com".to_string()),
+            ]
+        );
+
+        for invalid in [
+            "state=abc",
+            "code_challenge=x",
+            "prompt=login",
+            "response_mode=fragment",
+            "nonce=abc",
+            "domain_hint",
+        ] {
+            config.extra_auth_params = vec![invalid.to_string()];
+            assert!(config.authorization_params().is_err(), "{}", invalid);
+        }
+
+        // Values may contain commas
+        let claims = r#"claims={"id_token":{"acr":{"values":["a","b"]}}}"#;
+        let cli = Cli::try_parse_from([
+            "authful-mcp-proxy-rs",
+            "--auth-param",
+            claims,
+            "--auth-param",
+            "ui_locales=de,en",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.config.extra_auth_params,
+            vec![claims, "ui_locales=de,en"]
+        );
+    }
+
+    #[test]
+    fn test_validate_static_credential_modes() {
+        let mut config = Config {
+            backend_url: "https://backend.example.com".to_string(),
+            auth: AuthMode::ApiKey,
+            credential: Some("key-1".to_string()),
+            ..Default::default()
+        };
+        // No OIDC issuer or client ID needed
+        assert!(config.validate().is_ok());
+
+        config.api_key_header = Some("Bad Header".to_string());
+        assert!(config.validate().is_err());
+        config.api_key_header = Some("X-Api-Token".to_string());
+
+        config.credential_command = Some("pass show mcp".to_string());
+        assert!(config.validate().is_err());
+
+        config.credential_command = None;
+        config.auth = AuthMode::Basic;
+        assert!(config.validate().is_err());
+        config.basic_user = Some("svc".to_string());
+        assert!(config.validate().is_ok());
+
+        config.auth = AuthMode::Command;
+        assert!(config.validate().is_err());
+        config.credential = None;
+        config.credential_command = Some("mcp-token --json".to_string());
+        assert!(config.validate().is_ok());
+
+        config.auth = AuthMode::Oidc;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_sigv4() {
+        let mut config = Config {
+            backend_url: "https://abc.lambda-url.eu-west-1.on.aws/mcp".to_string(),
+            auth: AuthMode::None,
+            sigv4: true,
+            aws_region: Some("eu-west-1".to_string()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.aws_region().unwrap(), "eu-west-1");
+
+        config.sigv4_token_header = Some("X Token".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_tls_client_auth_replaces_client_secret() {
+        let mut config = Config {
+            backend_url: "https://mcp.example.com".to_string(),
+            oidc_issuer_url: "https://auth.example.com".to_string(),
+            oidc_client_id: "client".to_string(),
+            oidc_client_secret: Some("secret".to_string()),
+            tls_client_cert: Some(PathBuf::from("client.pem")),
+            ..Default::default()
+        };
+        // A certificate for an mTLS backend leaves the secret in use
+        assert_eq!(config.client_secret(), Some("secret"));
+
+        config.oidc_tls_client_auth = true;
+        assert!(config.validate().is_ok());
+        assert_eq!(config.client_secret(), None);
+
+        config.tls_client_cert = None;
+        assert!(config.validate().is_err());
+    }
 }
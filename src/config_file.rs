@@ -0,0 +1,276 @@
+//! Configuration file support
+//!
+//! Lets users define identity providers and named MCP servers once, instead of
+//! duplicating issuer, client ID and scopes across every MCP client `env` block:
+//!
+//! ```toml
+//! [providers.corp]
+//! issuer_url = "https://auth.company.com"
+//! client_id = "mcp-proxy"
+//!
+//! [servers.tools]
+//! backend_url = "https://mcp.company.com/mcp"
+//! provider = "corp"
+//! scopes = "openid profile mcp:read"
+//! prompt = "select_account"
+//!
+//! [servers.tools.extra_auth_params]
+//! domain_hint = "company.com"
+//! ```
+//!
+//! A server is selected with `--server tools`. Values from the file have the
+//! lowest precedence (CLI > env > file). Files ending in `.json` are parsed as
+//! JSON, everything else as TOML.
+
+use crate::error::{ProxyError, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Parsed configuration file
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Shared identity providers, referenced by servers
+    #[serde(default)]
+    pub providers: BTreeMap<String, ProviderEntry>,
+
+    /// Named MCP servers
+    #[serde(default)]
+    pub servers: BTreeMap<String, ServerEntry>,
+}
+
+/// Identity provider settings shared by several servers
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderEntry {
+    pub issuer_url: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub scopes: Option<String>,
+    pub redirect_url: Option<String>,
+    pub callback_ports: Option<String>,
+    pub prompt: Option<String>,
+    pub login_hint: Option<String>,
+    pub acr_values: Option<String>,
+    pub max_age: Option<u64>,
+    pub ui_locales: Option<String>,
+    #[serde(default)]
+    pub extra_auth_params: BTreeMap<String, String>,
+}
+
+/// Named MCP server
+///
+/// Identity settings given here override those of the referenced provider.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerEntry {
+    pub backend_url: Option<String>,
+    pub provider: Option<String>,
+    pub issuer_url: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub scopes: Option<String>,
+    pub redirect_url: Option<String>,
+    pub callback_ports: Option<String>,
+    pub prompt: Option<String>,
+    pub login_hint: Option<String>,
+    pub acr_values: Option<String>,
+    pub max_age: Option<u64>,
+    pub ui_locales: Option<String>,
+    #[serde(default)]
+    pub extra_auth_params: BTreeMap<String, String>,
+    /// Headers for every backend request (`--header`)
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub profile: Option<String>,
+}
+
+impl ConfigFile {
+    /// Default configuration file location
+    ///
+    /// Returns: $XDG_CONFIG_HOME/authful-mcp-proxy/config.toml or
+    ///          ~/.config/authful-mcp-proxy/config.toml on Linux/macOS
+    ///          %APPDATA%\authful-mcp-proxy\config.toml on Windows
+    pub fn default_path() -> Result<PathBuf> {
+        let config_dir = if cfg!(windows) {
+            std::env::var("APPDATA").map(PathBuf::from)
+        } else {
+            std::env::var("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|_| std::env::var("HOME").map(|h| PathBuf::from(h).join(".config")))
+        }
+        .map_err(|_| ProxyError::Config("Cannot determine config directory".to_string()))?;
+
+        Ok(config_dir.join("authful-mcp-proxy").join("config.toml"))
+    }
+
+    /// Load and parse a configuration file
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            ProxyError::Config(format!(
+                "Failed to read config file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+        Self::parse(&contents, path.extension().is_some_and(|ext| ext == "json")).map_err(|e| {
+            ProxyError::Config(format!("Invalid config file {}: {}", path.display(), e))
+        })
+    }
+
+    fn parse(contents: &str, json: bool) -> std::result::Result<Self, String> {
+        if json {
+            serde_json::from_str(contents).map_err(|e| e.to_string())
+        } else {
+            toml::from_str(contents).map_err(|e| e.to_string())
+        }
+    }
+
+    /// Look up a server and merge in the settings of its provider
+    pub fn resolve_server(&self, name: &str) -> Result<ServerEntry> {
+        let server = self.servers.get(name).ok_or_else(|| {
+            let known: Vec<&str> = self.servers.keys().map(String::as_str).collect();
+            ProxyError::Config(format!(
+                "Unknown server '{}' (configured servers: {})",
+                name,
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            ))
+        })?;
+
+        let Some(ref provider_name) = server.provider else {
+            return Ok(server.clone());
+        };
+
+        let provider = self.providers.get(provider_name).ok_or_else(|| {
+            ProxyError::Config(format!(
+                "Server '{}' references unknown provider '{}'",
+                name, provider_name
+            ))
+        })?;
+
+        Ok(ServerEntry {
+            issuer_url: server.issuer_url.clone().or(provider.issuer_url.clone()),
+            client_id: server.client_id.clone().or(provider.client_id.clone()),
+            client_secret: server
+                .client_secret
+                .clone()
+                .or(provider.client_secret.clone()),
+            scopes: server.scopes.clone().or(provider.scopes.clone()),
+            redirect_url: server
+                .redirect_url
+                .clone()
+                .or(provider.redirect_url.clone()),
+            callback_ports: server
+                .callback_ports
+                .clone()
+                .or(provider.callback_ports.clone()),
+            prompt: server.prompt.clone().or(provider.prompt.clone()),
+            login_hint: server.login_hint.clone().or(provider.login_hint.clone()),
+            acr_values: server.acr_values.clone().or(provider.acr_values.clone()),
+            max_age: server.max_age.or(provider.max_age),
+            ui_locales: server.ui_locales.clone().or(provider.ui_locales.clone()),
+            extra_auth_params: provider
+                .extra_auth_params
+                .clone()
+                .into_iter()
+                .chain(server.extra_auth_params.clone())
+                .collect(),
+            ..server.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = r#"
+        [providers.corp]
+        issuer_url = "https://auth.company.com"
+        client_id = "mcp-proxy"
+        scopes = "openid profile"
+        prompt = "select_account"
+        extra_auth_params = { domain_hint = "company.com", resource_hint = "mcp" }
+
+        [servers.tools]
+        backend_url = "https://mcp.company.com/mcp"
+        provider = "corp"
+        scopes = "openid mcp:read"
+        max_age = 3600
+
+        [servers.tools.extra_auth_params]
+        domain_hint = "tools.company.com"
+
+        [servers.admin]
+        backend_url = "https://admin.company.com/mcp"
+        provider = "corp"
+        profile = "admin"
+        headers = { X-Tenant = "admin", X-User = "{{claims.email}}" }
+
+        [servers.broken]
+        backend_url = "https://broken.company.com/mcp"
+        provider = "missing"
+    "#;
+
+    #[test]
+    fn test_resolve_server_merges_provider() {
+        let file = ConfigFile::parse(EXAMPLE, false).unwrap();
+
+        let tools = file.resolve_server("tools").unwrap();
+        assert_eq!(
+            tools.issuer_url.as_deref(),
+            Some("https://auth.company.com")
+        );
+        assert_eq!(tools.client_id.as_deref(), Some("mcp-proxy"));
+        // Server settings override provider settings
+        assert_eq!(tools.scopes.as_deref(), Some("openid mcp:read"));
+        assert_eq!(tools.prompt.as_deref(), Some("select_account"));
+        assert_eq!(tools.max_age, Some(3600));
+        assert_eq!(
+            tools
+                .extra_auth_params
+                .get("domain_hint")
+                .map(String::as_str),
+            Some("tools.company.com")
+        );
+        assert_eq!(
+            tools
+                .extra_auth_params
+                .get("resource_hint")
+                .map(String::as_str),
+            Some("mcp")
+        );
+
+        let admin = file.resolve_server("admin").unwrap();
+        assert_eq!(admin.scopes.as_deref(), Some("openid profile"));
+        assert_eq!(admin.profile.as_deref(), Some("admin"));
+        assert_eq!(admin.headers["X-User"], "{{claims.email}}");
+    }
+
+    #[test]
+    fn test_resolve_server_errors() {
+        let file = ConfigFile::parse(EXAMPLE, false).unwrap();
+
+        let err = file.resolve_server("nope").unwrap_err().to_string();
+        assert!(err.contains("admin, broken, tools"));
+
+        let err = file.resolve_server("broken").unwrap_err().to_string();
+        assert!(err.contains("unknown provider 'missing'"));
+    }
+
+    #[test]
+    fn test_parse_json_and_reject_unknown_fields() {
+        let json = r#"{"servers": {"tools": {"backend_url": "https://mcp.example.com"}}}"#;
+        let file = ConfigFile::parse(json, true).unwrap();
+        assert!(file.servers.contains_key("tools"));
+
+        let typo = "[servers.tools]\nbackend_ur = \"https://mcp.example.com\"";
+        assert!(ConfigFile::parse(typo, false).is_err());
+    }
+}
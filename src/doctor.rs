@@ -0,0 +1,642 @@
+//! `doctor` diagnostics command
+//!
+//! Runs the steps the proxy depends on one by one (configuration, OIDC
+//! discovery, callback port, token cache, backend connectivity) and reports
+//! each as pass/warn/fail with a remediation hint, so that common problems can
+//! be diagnosed without reading debug logs.
+//!
+//! With a static credential mode (`--auth bearer|api-key|basic`) the OIDC
+//! steps are skipped and the credential is checked instead.
+
+use crate::auth::{AuthProvider, CommandAuth, NoAuth, StaticAuth};
+use crate::config::{AuthMode, Config};
+use crate::error::Result;
+use crate::http::{HttpClientFactory, Target};
+use crate::oidc::callback::CallbackListener;
+use crate::oidc::dpop::{DpopKey, DPOP_HEADER};
+use crate::oidc::{jwt, OidcConfig, TokenCacheKey, TokenInfo};
+use crate::sigv4::SigV4Signer;
+use std::io::Write;
+use std::time::Duration;
+
+const BACKEND_TIMEOUT_SECS: u64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+    Skip,
+}
+
+impl Status {
+    fn label(self) -> &'static str {
+        match self {
+            Status::Pass => "PASS",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+            Status::Skip => "SKIP",
+        }
+    }
+}
+
+/// Outcome of a single diagnostic step
+struct Check {
+    status: Status,
+    title: String,
+    details: Vec<String>,
+    hint: Option<String>,
+}
+
+impl Check {
+    fn new(status: Status, title: impl Into<String>) -> Self {
+        Self {
+            status,
+            title: title.into(),
+            details: Vec::new(),
+            hint: None,
+        }
+    }
+
+    fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.details.push(detail.into());
+        self
+    }
+
+    fn hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+}
+
+/// Prints checks as they complete and tracks failures
+struct Report {
+    failures: usize,
+}
+
+impl Report {
+    fn add(&mut self, check: Check) {
+        if check.status == Status::Fail {
+            self.failures += 1;
+        }
+
+        let mut stdout = std::io::stdout();
+        let _ = writeln!(stdout, "[{}] {}", check.status.label(), check.title);
+        for detail in &check.details {
+            let _ = writeln!(stdout, "       {}", detail);
+        }
+        if let Some(ref hint) = check.hint {
+            let _ = writeln!(stdout, "       hint: {}", hint);
+        }
+        let _ = stdout.flush();
+    }
+
+    /// Print the summary line; returns `true` if no check failed
+    fn summary(self) -> bool {
+        let mut stdout = std::io::stdout();
+        let _ = writeln!(stdout);
+        if self.failures == 0 {
+            let _ = writeln!(stdout, "All checks passed.");
+        } else {
+            let _ = writeln!(stdout, "{} check(s) failed.", self.failures);
+        }
+
+        self.failures == 0
+    }
+}
+
+/// Run all diagnostics; returns `true` if no check failed
+pub async fn run(config: &Config) -> bool {
+    let mut report = Report { failures: 0 };
+
+    // 1. Configuration
+    if let Err(e) = config.validate() {
+        report.add(
+            Check::new(Status::Fail, "Configuration")
+                .detail(e.to_string())
+                .hint(if config.auth == AuthMode::Oidc {
+                    "Set MCP_BACKEND_URL, OIDC_ISSUER_URL and OIDC_CLIENT_ID (or the matching CLI options)"
+                } else {
+                    "Set MCP_BACKEND_URL and one of MCP_PROXY_CREDENTIAL, MCP_PROXY_CREDENTIAL_FILE or MCP_PROXY_CREDENTIAL_COMMAND"
+                }),
+        );
+        return false;
+    }
+    let check = Check::new(Status::Pass, "Configuration")
+        .detail(format!("Backend URL: {}", config.backend_url));
+    report.add(if config.auth == AuthMode::Oidc {
+        check
+            .detail(format!("Issuer:      {}", config.oidc_issuer_url))
+            .detail(format!("Client ID:   {}", config.oidc_client_id))
+            .detail(format!("Scopes:      {}", config.scopes().join(" ")))
+    } else {
+        check.detail(format!("Auth:        {}", config.auth.name()))
+    });
+
+    // TLS settings (client certificate, trust roots)
+    let http = match HttpClientFactory::from_config(config) {
+        Ok(http) => http,
+        Err(e) => {
+            report.add(
+                Check::new(Status::Fail, "TLS settings")
+                    .detail(e.to_string())
+                    .hint("Check the --tls-* paths; certificates and keys must be PEM encoded"),
+            );
+            return false;
+        }
+    };
+    if http.has_client_certificate() {
+        report.add(
+            Check::new(Status::Pass, "TLS client certificate").detail(format!(
+                "{}",
+                config
+                    .tls_client_cert
+                    .as_deref()
+                    .unwrap_or_else(|| std::path::Path::new("-"))
+                    .display()
+            )),
+        );
+    }
+
+    if config.auth != AuthMode::Oidc {
+        return run_static(config, &http, report).await;
+    }
+
+    // 2. OIDC discovery
+    report.add(check_discovery(config, &http).await);
+
+    // 3. Callback port
+    report.add(check_callback_port(config).await);
+
+    // 4. Token cache
+    let key = TokenCacheKey::from_config(config);
+    let (cache_check, tokens) = check_token_cache(&key);
+    report.add(cache_check);
+
+    // 5. Cached access token claims
+    report.add(check_token_claims(tokens.as_ref()));
+
+    // 6. Backend MCP initialize
+    let Some(signer) = check_sigv4(config, &mut report) else {
+        return report.summary();
+    };
+    report.add(check_backend(config, &http, tokens.as_ref(), None, signer.as_ref()).await);
+
+    report.summary()
+}
+
+/// Remaining checks of the static credential modes
+async fn run_static(config: &Config, http: &HttpClientFactory, mut report: Report) -> bool {
+    let skipped = format!("Not used with --auth {}", config.auth.name());
+    for title in [
+        "OIDC discovery",
+        "Callback port",
+        "Token cache",
+        "Access token claims",
+    ] {
+        report.add(Check::new(Status::Skip, title).detail(skipped.clone()));
+    }
+
+    let (check, auth) = check_credential(config).await;
+    report.add(check);
+    let Some(auth) = auth else {
+        return report.summary();
+    };
+
+    let Some(signer) = check_sigv4(config, &mut report) else {
+        return report.summary();
+    };
+    report.add(check_backend(config, http, None, Some(auth.as_ref()), signer.as_ref()).await);
+
+    report.summary()
+}
+
+/// Load the SigV4 signer if signing is enabled; `None` if that failed
+fn check_sigv4(config: &Config, report: &mut Report) -> Option<Option<SigV4Signer>> {
+    if !config.sigv4 {
+        return Some(None);
+    }
+
+    let title = "AWS SigV4 credentials";
+    match SigV4Signer::from_config(config) {
+        Ok(signer) => {
+            report.add(Check::new(Status::Pass, title).detail(format!(
+                "Region {}, service {}",
+                config.aws_region().unwrap_or_default(),
+                config
+                    .aws_service
+                    .as_deref()
+                    .unwrap_or(crate::sigv4::DEFAULT_SERVICE)
+            )));
+            Some(Some(signer))
+        }
+        Err(e) => {
+            report.add(
+                Check::new(Status::Fail, title)
+                    .detail(e.to_string())
+                    .hint("Set AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY or use --aws-profile with ~/.aws/credentials"),
+            );
+            None
+        }
+    }
+}
+
+async fn check_credential(config: &Config) -> (Check, Option<Box<dyn AuthProvider>>) {
+    let title = "Credential";
+    let result: Result<(String, Box<dyn AuthProvider>)> = match config.auth {
+        AuthMode::Command => match CommandAuth::from_config(config) {
+            Ok(auth) => auth.token().await.map(|token| {
+                let expiry = match token.expires_at {
+                    Some(expires_at) => chrono::DateTime::from_timestamp(expires_at as i64, 0)
+                        .map(|dt| format!("expires {}", dt.format("%Y-%m-%d %H:%M:%S UTC")))
+                        .unwrap_or_else(|| format!("expires {}", expires_at)),
+                    None => "no expiry".to_string(),
+                };
+                let detail = format!("{} token from command ({})", token.token_type, expiry);
+                (detail, Box::new(auth) as Box<dyn AuthProvider>)
+            }),
+            Err(e) => Err(e),
+        },
+        AuthMode::None => {
+            let check = Check::new(Status::Skip, title).detail("Not used with --auth none");
+            return (check, Some(Box::new(NoAuth)));
+        }
+        _ => StaticAuth::from_config(config).await.map(|auth| {
+            let detail = format!("Sent in header {}", auth.header());
+            (detail, Box::new(auth) as Box<dyn AuthProvider>)
+        }),
+    };
+
+    match result {
+        Ok((detail, auth)) => (Check::new(Status::Pass, title).detail(detail), Some(auth)),
+        Err(e) => (
+            Check::new(Status::Fail, title)
+                .detail(e.to_string())
+                .hint("Check the credential file or run the credential command manually"),
+            None,
+        ),
+    }
+}
+
+async fn check_discovery(config: &Config, http: &HttpClientFactory) -> Check {
+    let oidc_config = match http.client(Target::Provider) {
+        Ok(client) => OidcConfig::discover(&client, &config.oidc_issuer_url).await,
+        Err(e) => Err(e),
+    };
+    let oidc_config = match oidc_config {
+        Ok(oidc_config) => oidc_config,
+        Err(e) => {
+            return Check::new(Status::Fail, "OIDC discovery")
+                .detail(e.to_string())
+                .hint(format!(
+                    "Check that {}/.well-known/openid-configuration is reachable and that the issuer URL has no extra path segments",
+                    config.oidc_issuer_url.trim_end_matches('/')
+                ));
+        }
+    };
+
+    let optional = |endpoint: &Option<String>| {
+        endpoint
+            .clone()
+            .unwrap_or_else(|| "(not supported)".to_string())
+    };
+    let list = |values: &Option<Vec<String>>| {
+        values
+            .as_ref()
+            .map(|v| v.join(" "))
+            .unwrap_or_else(|| "(not advertised)".to_string())
+    };
+
+    let mut check = Check::new(Status::Pass, "OIDC discovery")
+        .detail(format!("issuer:                 {}", oidc_config.issuer))
+        .detail(format!(
+            "authorization_endpoint: {}",
+            oidc_config.authorization_endpoint
+        ))
+        .detail(format!(
+            "token_endpoint:         {}",
+            oidc_config.token_endpoint
+        ))
+        .detail(format!(
+            "userinfo_endpoint:      {}",
+            optional(&oidc_config.userinfo_endpoint)
+        ))
+        .detail(format!(
+            "revocation_endpoint:    {}",
+            optional(&oidc_config.revocation_endpoint)
+        ))
+        .detail(format!(
+            "end_session_endpoint:   {}",
+            optional(&oidc_config.end_session_endpoint)
+        ))
+        .detail(format!(
+            "grant types:            {}",
+            list(&oidc_config.grant_types_supported)
+        ))
+        .detail(format!(
+            "PKCE methods:           {}",
+            list(&oidc_config.code_challenge_methods_supported)
+        ));
+
+    if oidc_config.issuer.trim_end_matches('/') != config.oidc_issuer_url.trim_end_matches('/') {
+        check.status = Status::Warn;
+        check = check.hint(format!(
+            "Discovered issuer differs from configured issuer {}; use the discovered value",
+            config.oidc_issuer_url
+        ));
+    } else if let Some(ref methods) = oidc_config.code_challenge_methods_supported {
+        if !methods.iter().any(|m| m == "S256") {
+            check.status = Status::Warn;
+            check = check.hint("Provider does not advertise PKCE S256, which the proxy requires");
+        }
+    }
+
+    check
+}
+
+async fn check_callback_port(config: &Config) -> Check {
+    let redirect_url = config.redirect_url();
+    let ports = match config.callback_ports() {
+        Ok(ports) => ports,
+        Err(e) => return Check::new(Status::Fail, "Callback port").detail(e.to_string()),
+    };
+
+    match CallbackListener::bind(&redirect_url, ports.as_deref()).await {
+        Ok(listener) => Check::new(
+            Status::Pass,
+            format!("Callback port ({})", listener.redirect_uri()),
+        ),
+        Err(e) => Check::new(Status::Fail, format!("Callback port ({})", redirect_url))
+            .detail(e.to_string())
+            .hint("Register every redirect URI you may use with your provider"),
+    }
+}
+
+fn check_token_cache(key: &TokenCacheKey) -> (Check, Option<TokenInfo>) {
+    let path = match TokenInfo::get_token_file_path(key) {
+        Ok(path) => path,
+        Err(e) => {
+            return (
+                Check::new(Status::Fail, "Token cache")
+                    .detail(e.to_string())
+                    .hint("Set HOME (or USERPROFILE on Windows) to a writable directory"),
+                None,
+            );
+        }
+    };
+
+    let tokens = match TokenInfo::load_from_disk(key) {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            return (
+                Check::new(Status::Fail, "Token cache")
+                    .detail(format!("{}: {}", path.display(), e))
+                    .hint("The token file is unreadable or corrupt; run `logout` or delete it"),
+                None,
+            );
+        }
+    };
+
+    let Some(tokens) = tokens else {
+        return (
+            Check::new(Status::Warn, "Token cache")
+                .detail(format!("No cached tokens at {}", path.display()))
+                .hint("Run `login` to authenticate before starting the MCP client"),
+            None,
+        );
+    };
+
+    let mut check = Check::new(Status::Pass, "Token cache").detail(path.display().to_string());
+
+    #[cfg(unix)]
+    if let Ok(metadata) = std::fs::metadata(&path) {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = metadata.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            check.status = Status::Warn;
+            check = check
+                .detail(format!("Permissions: {:o}", mode))
+                .hint(format!(
+                    "Token file is readable by other users: chmod 600 {}",
+                    path.display()
+                ));
+        }
+    }
+
+    if tokens.is_valid() {
+        check = check.detail("Access token is valid");
+    } else if tokens.can_refresh() {
+        check = check.detail("Access token expired, will be refreshed");
+    }
+
+    (check, Some(tokens))
+}
+
+fn check_token_claims(tokens: Option<&TokenInfo>) -> Check {
+    let Some(tokens) = tokens else {
+        return Check::new(Status::Skip, "Access token claims").detail("No cached token");
+    };
+
+    let Some(claims) = jwt::decode_claims(&tokens.access_token) else {
+        return Check::new(Status::Pass, "Access token claims")
+            .detail("Opaque access token (not a JWT), claims cannot be decoded");
+    };
+
+    let mut check = Check::new(Status::Pass, "Access token claims");
+    for name in ["iss", "sub", "aud", "azp", "scope", "scp", "exp"] {
+        if let Some(value) = claims.get(name) {
+            let value = match value {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            check = check.detail(format!("{:<6} {}", format!("{}:", name), value));
+        }
+    }
+
+    check
+}
+
+async fn check_backend(
+    config: &Config,
+    http: &HttpClientFactory,
+    tokens: Option<&TokenInfo>,
+    credentials: Option<&dyn AuthProvider>,
+    signer: Option<&SigV4Signer>,
+) -> Check {
+    let title = format!("Backend MCP initialize ({})", config.backend_url);
+
+    let initialize = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": {
+            "protocolVersion": "2025-06-18",
+            "capabilities": {},
+            "clientInfo": {
+                "name": "authful-mcp-proxy-rs-doctor",
+                "version": env!("CARGO_PKG_VERSION")
+            }
+        }
+    });
+
+    let client = match http.client(Target::Backend) {
+        Ok(client) => client,
+        Err(e) => return Check::new(Status::Fail, title).detail(e.to_string()),
+    };
+    let mut request = client
+        .post(&config.backend_url)
+        .header("Content-Type", "application/json")
+        .header("Accept", "application/json, text/event-stream")
+        .timeout(Duration::from_secs(BACKEND_TIMEOUT_SECS))
+        .body(initialize.to_string());
+
+    // Only use a valid cached token: doctor never starts an interactive login
+    let authenticated = match tokens.filter(|t| t.is_valid()) {
+        Some(tokens) if config.dpop && tokens.is_dpop_bound() => {
+            let proof = DpopKey::load_or_generate(config.profile.as_deref()).and_then(|key| {
+                key.proof(
+                    "POST",
+                    &config.backend_url,
+                    Some(&tokens.access_token),
+                    None,
+                )
+            });
+            match proof {
+                Ok(proof) => {
+                    request = request
+                        .header("Authorization", format!("DPoP {}", tokens.access_token))
+                        .header(DPOP_HEADER, proof);
+                }
+                Err(e) => {
+                    return Check::new(Status::Fail, title)
+                        .detail(e.to_string())
+                        .hint("The DPoP key is unreadable; run `logout` and `login` again");
+                }
+            }
+            true
+        }
+        Some(tokens) => {
+            request = request.bearer_auth(&tokens.access_token);
+            true
+        }
+        None => credentials.is_some(),
+    };
+
+    let mut request = match request.build() {
+        Ok(request) => request,
+        Err(e) => return Check::new(Status::Fail, title).detail(e.to_string()),
+    };
+    if let Some(auth) = credentials {
+        if let Err(e) = auth.authorize(&mut request).await {
+            return Check::new(Status::Fail, title).detail(e.to_string());
+        }
+    }
+    match crate::headers::from_config(config) {
+        Ok(headers) => {
+            let claims = match (tokens, credentials) {
+                (Some(tokens), _) => Some(tokens.claims()),
+                (None, Some(auth)) => auth.claims().await,
+                (None, None) => None,
+            };
+            for header in headers {
+                if let Some(value) = header.render(claims.as_ref()) {
+                    request.headers_mut().insert(header.name().clone(), value);
+                }
+            }
+        }
+        Err(e) => return Check::new(Status::Fail, title).detail(e.to_string()),
+    }
+    if let Some(signer) = signer {
+        if let Err(e) = signer.sign(&mut request) {
+            return Check::new(Status::Fail, title).detail(e.to_string());
+        }
+    }
+
+    let response = match client.execute(request).await {
+        Ok(response) => response,
+        Err(e) => {
+            return Check::new(Status::Fail, title)
+                .detail(e.to_string())
+                .hint("Check the backend URL, network connectivity and any VPN/proxy settings");
+        }
+    };
+
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+
+    if status.is_success() {
+        let mut check = Check::new(Status::Pass, title).detail(format!("HTTP {}", status));
+        if let Some(server_info) = parse_server_info(&body) {
+            check = check.detail(server_info);
+        }
+        return check;
+    }
+
+    let check = Check::new(Status::Fail, title).detail(format!("HTTP {}", status));
+    match status {
+        reqwest::StatusCode::UNAUTHORIZED if credentials.is_some() => {
+            check.hint("The backend rejected the credential: check its value and the --auth mode")
+        }
+        reqwest::StatusCode::UNAUTHORIZED if authenticated => check.hint(
+            "The backend rejected the cached token: verify issuer, client ID and scopes, then run `login` again",
+        ),
+        reqwest::StatusCode::UNAUTHORIZED => {
+            check.hint("No valid cached token: run `login` first, then re-run `doctor`")
+        }
+        reqwest::StatusCode::FORBIDDEN => {
+            check.hint("The token lacks permissions: check the requested scopes (OIDC_SCOPES)")
+        }
+        reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::METHOD_NOT_ALLOWED => check.hint(
+            "The URL does not look like an MCP streamable HTTP endpoint (often it ends in /mcp)",
+        ),
+        _ => check.hint("Check the backend server logs"),
+    }
+}
+
+/// Extract "name version (protocol ...)" from an initialize response (JSON or SSE)
+fn parse_server_info(body: &str) -> Option<String> {
+    let json = body
+        .lines()
+        .find_map(|line| line.strip_prefix("data:"))
+        .unwrap_or(body);
+    let value: serde_json::Value = serde_json::from_str(json.trim()).ok()?;
+    let result = value.get("result")?;
+
+    let server = result.get("serverInfo");
+    let name = server
+        .and_then(|s| s.get("name"))
+        .and_then(|n| n.as_str())
+        .unwrap_or("unknown server");
+    let version = server
+        .and_then(|s| s.get("version"))
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+    let protocol = result
+        .get("protocolVersion")
+        .and_then(|p| p.as_str())
+        .unwrap_or("unknown");
+
+    Some(format!("Server: {} {} (protocol {})", name, version, protocol).replace("  ", " "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_server_info_json_and_sse() {
+        let json = r#"{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2025-06-18","serverInfo":{"name":"tools","version":"1.2.0"}}}"#;
+        assert_eq!(
+            parse_server_info(json).unwrap(),
+            "Server: tools 1.2.0 (protocol 2025-06-18)"
+        );
+
+        let sse = format!("event: message\ndata: {}\n\n", json);
+        assert_eq!(
+            parse_server_info(&sse).unwrap(),
+            "Server: tools 1.2.0 (protocol 2025-06-18)"
+        );
+
+        assert!(parse_server_info("not json").is_none());
+    }
+}
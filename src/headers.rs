@@ -0,0 +1,283 @@
+//! Custom backend headers
+//!
+//! `--header 'Name: value'` adds a header to every backend request (never to
+//! requests to the OIDC provider, which use a separate client). Values may
+//! contain:
+//!
+//! - `${VAR}`: environment variable, expanded once at startup
+//! - `{{claims.NAME}}`: claim of the current token (dotted paths reach into
+//!   nested objects), rendered per request so renewed tokens are reflected
+//!
+//! A header whose claim is missing is left out of the request.
+
+use crate::auth::credential::DEFAULT_API_KEY_HEADER;
+use crate::config::{AuthMode, Config};
+use crate::error::{ProxyError, Result};
+use crate::sigv4::DEFAULT_TOKEN_HEADER;
+use reqwest::header::{HeaderName, HeaderValue};
+use serde_json::{Map, Value};
+
+/// Headers the proxy sets itself
+const RESERVED_HEADERS: &[&str] = &[
+    "authorization",
+    "dpop",
+    "host",
+    "content-length",
+    "content-type",
+    "accept",
+    "mcp-session-id",
+    "mcp-protocol-version",
+    "x-amz-date",
+    "x-amz-security-token",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    /// Path below `claims.`
+    Claim(Vec<String>),
+}
+
+/// Header with a value template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderTemplate {
+    name: HeaderName,
+    parts: Vec<Part>,
+}
+
+impl HeaderTemplate {
+    /// Parse `Name: value`, expanding environment variables
+    pub fn parse(spec: &str) -> Result<Self> {
+        let invalid =
+            |reason: &str| ProxyError::Config(format!("Invalid header '{}': {}", spec, reason));
+
+        let (name, value) = spec
+            .split_once(':')
+            .ok_or_else(|| invalid("expected 'Name: value'"))?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| invalid("invalid header name"))?;
+        if RESERVED_HEADERS.contains(&name.as_str()) {
+            return Err(invalid("this header is set by the proxy"));
+        }
+
+        let value = expand_env(value.trim()).map_err(|reason| invalid(&reason))?;
+        let parts = parse_template(&value).map_err(|reason| invalid(&reason))?;
+
+        let template = Self { name, parts };
+        // Check the literal parts now rather than on the first request
+        if !template.uses_claims() {
+            template
+                .render(None)
+                .ok_or_else(|| invalid("value contains characters not allowed in headers"))?;
+        }
+        Ok(template)
+    }
+
+    /// Header name
+    pub fn name(&self) -> &HeaderName {
+        &self.name
+    }
+
+    /// Whether the value depends on token claims
+    pub fn uses_claims(&self) -> bool {
+        self.parts.iter().any(|part| matches!(part, Part::Claim(_)))
+    }
+
+    /// Header value for the given claims; `None` if a claim is missing
+    pub fn render(&self, claims: Option<&Map<String, Value>>) -> Option<HeaderValue> {
+        let mut value = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => value.push_str(text),
+                Part::Claim(path) => value.push_str(&claim(claims?, path)?),
+            }
+        }
+        HeaderValue::from_str(&value).ok()
+    }
+}
+
+/// Header templates of the configuration
+pub fn from_config(config: &Config) -> Result<Vec<HeaderTemplate>> {
+    // Credential headers whose name depends on the configuration
+    let mut credential_headers = Vec::new();
+    if config.auth == AuthMode::ApiKey {
+        credential_headers.push(
+            config
+                .api_key_header
+                .as_deref()
+                .unwrap_or(DEFAULT_API_KEY_HEADER),
+        );
+    }
+    if config.sigv4 {
+        credential_headers.push(
+            config
+                .sigv4_token_header
+                .as_deref()
+                .unwrap_or(DEFAULT_TOKEN_HEADER),
+        );
+    }
+
+    config
+        .headers
+        .iter()
+        .map(|spec| {
+            let template = HeaderTemplate::parse(spec)?;
+            if credential_headers
+                .iter()
+                .any(|name| template.name().as_str().eq_ignore_ascii_case(name))
+            {
+                return Err(ProxyError::Config(format!(
+                    "Invalid header '{}': this header is set by the proxy",
+                    spec
+                )));
+            }
+            Ok(template)
+        })
+        .collect()
+}
+
+/// Replace `${VAR}` with the value of the environment variable
+fn expand_env(value: &str) -> std::result::Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| "unterminated '${'".to_string())?;
+        let name = &rest[start + 2..start + end];
+        let value =
+            std::env::var(name).map_err(|_| format!("environment variable {} is not set", name))?;
+        expanded.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Split a value into text and `{{claims.NAME}}` placeholders
+fn parse_template(value: &str) -> std::result::Result<Vec<Part>, String> {
+    let mut parts = Vec::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            parts.push(Part::Text(rest[..start].to_string()));
+        }
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| "unterminated '{{'".to_string())?;
+        let placeholder = rest[start + 2..start + end].trim();
+        let path = placeholder
+            .strip_prefix("claims.")
+            .filter(|path| !path.is_empty())
+            .ok_or_else(|| {
+                format!(
+                    "unknown placeholder '{{{{{}}}}}' (expected {{{{claims.NAME}}}})",
+                    placeholder
+                )
+            })?;
+        parts.push(Part::Claim(path.split('.').map(String::from).collect()));
+        rest = &rest[start + end + 2..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest.to_string()));
+    }
+    Ok(parts)
+}
+
+/// Claim value as header text (arrays are joined with commas)
+fn claim(claims: &Map<String, Value>, path: &[String]) -> Option<String> {
+    let (first, rest) = path.split_first()?;
+    let value = rest
+        .iter()
+        .try_fold(claims.get(first)?, |value, key| value.get(key))?;
+
+    let text = |value: &Value| match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    };
+    match value {
+        Value::Array(items) => items
+            .iter()
+            .map(text)
+            .collect::<Option<Vec<_>>>()
+            .map(|items| items.join(",")),
+        value => text(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_render_headers() {
+        std::env::set_var("AUTHFUL_MCP_PROXY_TEST_TENANT", "acme");
+        let tenant =
+            HeaderTemplate::parse("X-Tenant: ${AUTHFUL_MCP_PROXY_TEST_TENANT}-prod").unwrap();
+        assert_eq!(tenant.name(), "x-tenant");
+        assert!(!tenant.uses_claims());
+        assert_eq!(tenant.render(None).unwrap(), "acme-prod");
+
+        let claims = match serde_json::json!({
+            "email": "jane@example.com",
+            "roles": ["reader", "writer"],
+            "org": {"id": 42},
+        }) {
+            Value::Object(claims) => claims,
+            _ => unreachable!(),
+        };
+        let user = HeaderTemplate::parse("X-User: {{claims.email}}").unwrap();
+        assert!(user.uses_claims());
+        assert_eq!(user.render(Some(&claims)).unwrap(), "jane@example.com");
+        let org = HeaderTemplate::parse("X-Org: org-{{ claims.org.id }}").unwrap();
+        assert_eq!(org.render(Some(&claims)).unwrap(), "org-42");
+        let roles = HeaderTemplate::parse("X-Roles: {{claims.roles}}").unwrap();
+        assert_eq!(roles.render(Some(&claims)).unwrap(), "reader,writer");
+
+        // Missing claims leave the header out
+        let missing = HeaderTemplate::parse("X-Tid: {{claims.tid}}").unwrap();
+        assert!(missing.render(Some(&claims)).is_none());
+        assert!(user.render(None).is_none());
+    }
+
+    #[test]
+    fn test_reject_invalid_headers() {
+        for invalid in [
+            "X-Missing-Colon",
+            "Bad Name: value",
+            "Authorization: Bearer x",
+            "Content-Type: text/plain",
+            "Mcp-Session-Id: other",
+            "X-Amz-Date: 20240101T000000Z",
+            "X-Env: ${AUTHFUL_MCP_PROXY_TEST_UNSET}",
+            "X-Unknown: {{token}}",
+            "X-Open: {{claims.email",
+        ] {
+            assert!(HeaderTemplate::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_reject_credential_headers() {
+        let mut config = Config {
+            auth: AuthMode::ApiKey,
+            headers: vec!["X-API-Key: other".to_string()],
+            ..Default::default()
+        };
+        assert!(from_config(&config).is_err());
+
+        config.auth = AuthMode::Oidc;
+        assert_eq!(from_config(&config).unwrap().len(), 1);
+
+        config.sigv4 = true;
+        config.headers = vec!["X-Authorization: Bearer other".to_string()];
+        assert!(from_config(&config).is_err());
+        config.sigv4_token_header = Some("X-Token".to_string());
+        assert!(from_config(&config).is_ok());
+        config.headers = vec!["x-token: other".to_string()];
+        assert!(from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,339 @@
+//! Shared HTTP client factory
+//!
+//! Every outbound client (OIDC discovery, token calls, revocation and backend
+//! forwarding) is built here, so TLS and proxy settings apply consistently:
+//!
+//! - client certificate and key for mutual TLS (RFC 8705 `tls_client_auth`)
+//! - a CA bundle replacing the built-in trust roots
+//! - extra trust roots added to the built-in ones
+//! - outbound HTTP proxies, separately for the provider and the backend, with
+//!   credentials and a `no_proxy` list
+//!
+//! Without explicit proxy settings the usual `HTTP_PROXY` / `HTTPS_PROXY` /
+//! `NO_PROXY` environment variables apply.
+//!
+//! The local OAuth callback listener is loopback-only and does not use this.
+
+use crate::config::Config;
+use crate::error::{ProxyError, Result};
+use reqwest::{Certificate, Identity, NoProxy, Proxy};
+use std::path::Path;
+
+/// Destination of a client's requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// OIDC provider (discovery, token, revocation endpoints)
+    Provider,
+    /// MCP backend
+    Backend,
+}
+
+/// Proxy value that disables proxying for a target
+const DIRECT: &str = "direct";
+
+/// Outbound proxy of a target
+#[derive(Debug, Clone, Default)]
+enum ProxySetting {
+    /// Proxy from the environment variables
+    #[default]
+    System,
+    /// No proxy, even if the environment configures one
+    Direct,
+    /// Explicitly configured proxy
+    Explicit(Box<Proxy>),
+}
+
+/// Builds HTTP clients with the configured TLS and proxy settings
+#[derive(Clone)]
+pub struct HttpClientFactory {
+    identity: Option<Identity>,
+    roots: Vec<Certificate>,
+    built_in_roots: bool,
+    provider_proxy: ProxySetting,
+    backend_proxy: ProxySetting,
+}
+
+impl Default for HttpClientFactory {
+    fn default() -> Self {
+        Self {
+            identity: None,
+            roots: Vec::new(),
+            built_in_roots: true,
+            provider_proxy: ProxySetting::System,
+            backend_proxy: ProxySetting::System,
+        }
+    }
+}
+
+impl HttpClientFactory {
+    /// Load the TLS and proxy settings of the configuration (certificate files are read here)
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut factory = Self::default();
+
+        match (&config.tls_client_cert, &config.tls_client_key) {
+            (Some(cert), key) => {
+                // rustls expects the key and certificate chain in one PEM buffer
+                let mut pem = read_pem(cert)?;
+                if let Some(key) = key {
+                    pem.push(b'\n');
+                    pem.extend(read_pem(key)?);
+                }
+                factory.identity = Some(Identity::from_pem(&pem).map_err(|e| {
+                    ProxyError::Config(format!(
+                        "Invalid TLS client certificate or key {}: {}",
+                        cert.display(),
+                        e
+                    ))
+                })?);
+            }
+            (None, Some(_)) => {
+                return Err(ProxyError::Config(
+                    "--tls-client-key requires --tls-client-cert".to_string(),
+                ));
+            }
+            (None, None) => {}
+        }
+
+        if let Some(ref bundle) = config.tls_ca_bundle {
+            factory.roots.extend(read_certificates(bundle)?);
+            factory.built_in_roots = false;
+        }
+
+        if let Some(ref extra_roots) = config.tls_extra_roots {
+            factory.roots.extend(read_certificates(extra_roots)?);
+        }
+
+        let proxy =
+            |url: Option<&String>| proxy_setting(config, url.or(config.http_proxy.as_ref()));
+        factory.provider_proxy = proxy(config.idp_proxy.as_ref())?;
+        factory.backend_proxy = proxy(config.backend_proxy.as_ref())?;
+        if matches!(factory.provider_proxy, ProxySetting::System)
+            && matches!(factory.backend_proxy, ProxySetting::System)
+            && (config.proxy_user.is_some() || config.no_proxy.is_some())
+        {
+            tracing::warn!(
+                "--proxy-user and --no-proxy only apply to proxies set with --http-proxy, --idp-proxy or --backend-proxy"
+            );
+        }
+
+        Ok(factory)
+    }
+
+    /// Whether a client certificate is configured
+    pub fn has_client_certificate(&self) -> bool {
+        self.identity.is_some()
+    }
+
+    /// Client builder for a target, to add per-use settings (timeouts etc.)
+    pub fn builder(&self, target: Target) -> reqwest::ClientBuilder {
+        let mut builder = reqwest::Client::builder().tls_built_in_root_certs(self.built_in_roots);
+
+        for root in &self.roots {
+            builder = builder.add_root_certificate(root.clone());
+        }
+
+        if let Some(ref identity) = self.identity {
+            builder = builder.identity(identity.clone());
+        }
+
+        let proxy = match target {
+            Target::Provider => &self.provider_proxy,
+            Target::Backend => &self.backend_proxy,
+        };
+        match proxy {
+            ProxySetting::System => builder,
+            ProxySetting::Direct => builder.no_proxy(),
+            ProxySetting::Explicit(proxy) => builder.proxy(proxy.as_ref().clone()),
+        }
+    }
+
+    /// Client for a target
+    pub fn client(&self, target: Target) -> Result<reqwest::Client> {
+        Ok(self.builder(target).build()?)
+    }
+}
+
+/// Resolve the proxy of a target from its URL (falling back to `--http-proxy`)
+fn proxy_setting(config: &Config, url: Option<&String>) -> Result<ProxySetting> {
+    let Some(url) = url.map(|url| url.trim()) else {
+        return Ok(ProxySetting::System);
+    };
+    if url.eq_ignore_ascii_case(DIRECT) {
+        return Ok(ProxySetting::Direct);
+    }
+
+    let parsed = url::Url::parse(url)
+        .map_err(|e| ProxyError::Config(format!("Invalid proxy URL '{}': {}", url, e)))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(ProxyError::Config(format!(
+            "Unsupported proxy URL scheme '{}' (expected http or https)",
+            parsed.scheme()
+        )));
+    }
+
+    // Credentials in the URL are picked up by reqwest itself
+    let mut proxy = Proxy::all(url)
+        .map_err(|e| ProxyError::Config(format!("Invalid proxy URL '{}': {}", url, e)))?;
+    match (&config.proxy_user, &config.proxy_password) {
+        (Some(user), password) => {
+            proxy = proxy.basic_auth(user, password.as_deref().unwrap_or_default());
+        }
+        (None, Some(_)) => {
+            return Err(ProxyError::Config(
+                "--proxy-password requires --proxy-user".to_string(),
+            ));
+        }
+        (None, None) => {}
+    }
+
+    let proxy = proxy.no_proxy(config.no_proxy.as_deref().and_then(NoProxy::from_string));
+    Ok(ProxySetting::Explicit(Box::new(proxy)))
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path)
+        .map_err(|e| ProxyError::Config(format!("Failed to read {}: {}", path.display(), e)))
+}
+
+fn read_certificates(path: &Path) -> Result<Vec<Certificate>> {
+    let certificates = Certificate::from_pem_bundle(&read_pem(path)?).map_err(|e| {
+        ProxyError::Config(format!(
+            "Invalid certificate bundle {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+
+    if certificates.is_empty() {
+        return Err(ProxyError::Config(format!(
+            "No certificates found in {}",
+            path.display()
+        )));
+    }
+
+    Ok(certificates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+
+    #[test]
+    fn test_default_factory_builds_clients() {
+        let factory = HttpClientFactory::from_config(&Config::default()).unwrap();
+        assert!(!factory.has_client_certificate());
+        assert!(factory.client(Target::Provider).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_tls_settings_are_config_errors() {
+        let dir = std::env::temp_dir().join(format!("authful-http-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let not_pem = dir.join("not-a-cert.pem");
+        std::fs::write(&not_pem, "hello").unwrap();
+
+        let config = Config {
+            tls_ca_bundle: Some(not_pem.clone()),
+            ..Default::default()
+        };
+        let err = HttpClientFactory::from_config(&config).err().unwrap();
+        assert!(err.to_string().contains("No certificates found"));
+
+        let config = Config {
+            tls_client_key: Some(not_pem),
+            ..Default::default()
+        };
+        assert!(HttpClientFactory::from_config(&config).is_err());
+
+        let config = Config {
+            tls_extra_roots: Some(dir.join("missing.pem")),
+            ..Default::default()
+        };
+        assert!(HttpClientFactory::from_config(&config).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_proxy_settings_per_target() {
+        let config = Config {
+            http_proxy: Some("http://proxy.corp.example.com:3128".to_string()),
+            backend_proxy: Some("DIRECT".to_string()),
+            proxy_user: Some("alice".to_string()),
+            proxy_password: Some("secret".to_string()),
+            no_proxy: Some("localhost,.internal.example.com,10.0.0.0/8".to_string()),
+            ..Default::default()
+        };
+        let factory = HttpClientFactory::from_config(&config).unwrap();
+        assert!(matches!(factory.provider_proxy, ProxySetting::Explicit(_)));
+        assert!(matches!(factory.backend_proxy, ProxySetting::Direct));
+        assert!(factory.client(Target::Provider).is_ok());
+        assert!(factory.client(Target::Backend).is_ok());
+
+        let config = Config {
+            idp_proxy: Some("https://proxy.example.com".to_string()),
+            ..Default::default()
+        };
+        let factory = HttpClientFactory::from_config(&config).unwrap();
+        assert!(matches!(factory.provider_proxy, ProxySetting::Explicit(_)));
+        assert!(matches!(factory.backend_proxy, ProxySetting::System));
+
+        for invalid in ["proxy.example.com:3128", "ftp://proxy.example.com"] {
+            let config = Config {
+                http_proxy: Some(invalid.to_string()),
+                ..Default::default()
+            };
+            assert!(
+                HttpClientFactory::from_config(&config).is_err(),
+                "{}",
+                invalid
+            );
+        }
+
+        let config = Config {
+            http_proxy: Some("http://proxy.example.com".to_string()),
+            proxy_password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert!(HttpClientFactory::from_config(&config).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_requests_go_through_proxy() {
+        let mut proxy = mockito::Server::new_async().await;
+        // The backend host does not resolve: only the proxy can answer
+        let mock = proxy
+            .mock("GET", mockito::Matcher::Any)
+            .match_header(
+                "proxy-authorization",
+                format!(
+                    "Basic {}",
+                    base64::engine::general_purpose::STANDARD.encode("alice:secret")
+                )
+                .as_str(),
+            )
+            .with_status(204)
+            .create_async()
+            .await;
+
+        let config = Config {
+            backend_proxy: Some(proxy.url()),
+            proxy_user: Some("alice".to_string()),
+            proxy_password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let client = HttpClientFactory::from_config(&config)
+            .unwrap()
+            .client(Target::Backend)
+            .unwrap();
+        let response = client
+            .get("http://backend.invalid/mcp")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 204);
+        mock.assert_async().await;
+    }
+}
@@ -2,8 +2,17 @@
 //!
 //! Core library for the authful MCP proxy with OIDC authentication.
 
+pub mod auth;
+pub mod client_config;
+pub mod commands;
 pub mod config;
+pub mod config_file;
+pub mod doctor;
 pub mod error;
+pub mod headers;
+pub mod http;
 pub mod middleware;
 pub mod oidc;
 pub mod proxy;
+pub mod retry;
+pub mod sigv4;
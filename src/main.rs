@@ -3,14 +3,9 @@
 //! A Rust implementation of the authful MCP proxy that bridges remote HTTP MCP servers
 //! with OIDC authentication to local stdio transport for MCP clients like Claude Desktop.
 
-mod config;
-mod error;
-mod middleware;
-mod oidc;
-mod proxy;
-
-use config::Config;
-use error::Result;
+use authful_mcp_proxy_rs::config::{AuthMode, Cli, Command, Config};
+use authful_mcp_proxy_rs::error::{self, Result};
+use authful_mcp_proxy_rs::{auth, commands, doctor, proxy};
 use tracing::{error, info};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
@@ -37,7 +32,7 @@ fn setup_logging(config: &Config) -> Option<String> {
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
         let level = config.log_level();
         EnvFilter::new(format!(
-            "authful_mcp_proxy_ng={},\
+            "authful_mcp_proxy_rs={},\
              reqwest={},\
              reqwest_middleware={},\
              hyper={},\
@@ -81,21 +76,74 @@ fn setup_logging(config: &Config) -> Option<String> {
     }
 }
 
+/// Write an error to stderr explicitly (even if logging is disabled) and exit
+fn exit_with_error(context: &str, e: impl std::fmt::Display) -> ! {
+    use std::io::Write;
+    let mut stderr = std::io::stderr();
+    let _ = writeln!(stderr, "{}: {}", context, e);
+    let _ = stderr.flush();
+    std::process::exit(1);
+}
+
 #[tokio::main]
 async fn main() {
-    let config = Config::parse_args();
+    let mut cli = Cli::parse_args();
+    if let Err(e) = cli.config_mut().apply_config_file() {
+        exit_with_error("Configuration error", e);
+    }
+
+    let result = match cli.command {
+        Some(Command::Run(config)) => return run(config).await,
+        None => return run(cli.config).await,
+        Some(Command::Login(config)) => {
+            setup_logging(&config);
+            commands::login(&config).await
+        }
+        Some(Command::Status(args)) => {
+            setup_logging(&args.config);
+            commands::status(&args)
+        }
+        Some(Command::Token(config)) => {
+            setup_logging(&config);
+            commands::token(&config).await
+        }
+        Some(Command::Logout(args)) => {
+            setup_logging(&args.config);
+            commands::logout(&args).await
+        }
+        Some(Command::CompleteLogin(args)) => {
+            setup_logging(&args.config);
+            commands::complete_login(&args).await
+        }
+        Some(Command::PrintConfig(args)) => {
+            setup_logging(&args.config);
+            commands::print_config(&args)
+        }
+        Some(Command::Install(args)) => {
+            setup_logging(&args.client.config);
+            commands::install(&args)
+        }
+        Some(Command::Doctor(config)) => {
+            setup_logging(&config);
+            if !doctor::run(&config).await {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+    };
 
+    if let Err(e) = result {
+        exit_with_error("Error", e);
+    }
+}
+
+async fn run(config: Config) {
     // Set up logging
     let log_file_path = setup_logging(&config);
 
     // Validate configuration
     if let Err(e) = config.validate() {
-        // Write error to stderr explicitly, even if logging is disabled
-        use std::io::Write;
-        let mut stderr = std::io::stderr();
-        let _ = writeln!(stderr, "Configuration error: {}", e);
-        let _ = stderr.flush();
-        std::process::exit(1);
+        exit_with_error("Configuration error", e);
     }
 
     // Show banner and info unless silent
@@ -110,10 +158,17 @@ async fn main() {
             info!("Logging to file: {}", log_path);
         }
         info!("Backend URL: {}", config.backend_url);
-        info!("OIDC Issuer: {}", config.oidc_issuer_url);
-        info!("Client ID: {}", config.oidc_client_id);
-        info!("Scopes: {}", config.scopes().join(" "));
-        info!("Redirect URL: {}", config.redirect_url());
+        if config.auth == AuthMode::Oidc {
+            info!("OIDC Issuer: {}", config.oidc_issuer_url);
+            info!("Client ID: {}", config.oidc_client_id);
+            info!("Scopes: {}", config.scopes().join(" "));
+            info!("Redirect URL: {}", config.redirect_url());
+            if let Some(ref profile) = config.profile {
+                info!("Profile: {}", profile);
+            }
+        } else {
+            info!("Authentication: {}", config.auth.name());
+        }
 
         let _ = writeln!(stderr);
         let _ = stderr.flush();
@@ -121,34 +176,29 @@ async fn main() {
 
     // Run the proxy
     if let Err(e) = run_proxy(config).await {
-        // Write error to stderr explicitly
-        use std::io::Write;
-        let mut stderr = std::io::stderr();
-        let _ = writeln!(stderr, "Proxy error: {}", e);
-        let _ = stderr.flush();
-        std::process::exit(1);
+        exit_with_error("Proxy error", e);
     }
 }
 
 async fn run_proxy(config: Config) -> Result<()> {
-    info!("Initializing OIDC client...");
+    info!("Initializing authentication...");
 
-    // Initialize OIDC client
-    let oidc_client = oidc::OidcClient::new(
-        config.oidc_issuer_url.clone(),
-        config.oidc_client_id.clone(),
-        config.oidc_client_secret.clone(),
-        config.scopes(),
-        config.redirect_url(),
-    )
-    .await?;
+    // Initialize the auth provider (runs OIDC discovery only in OIDC mode)
+    let auth_provider = auth::from_config(&config).await?;
 
-    info!("OIDC client initialized");
+    info!("Authentication initialized");
+
+    if !config.silent && config.auth == AuthMode::Oidc {
+        match auth_provider.identity().await {
+            Some(identity) => info!("Signed in as: {}", identity),
+            None => info!("Not signed in yet - login starts with the first request"),
+        }
+    }
 
     // Start MCP proxy server
     let proxy_handle = tokio::spawn({
         let config = config.clone();
-        async move { proxy::run_proxy_server(config, oidc_client).await }
+        async move { proxy::run_proxy_server(config, auth_provider).await }
     });
 
     // Wait for shutdown signal
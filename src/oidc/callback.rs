@@ -2,69 +2,383 @@
 //!
 //! Temporary HTTP server that handles the OAuth authorization callback.
 //! Runs on localhost and receives the authorization code from the OIDC provider.
+//!
+//! The listener is bound before the authorization URL is built, so the
+//! redirect URI sent to the provider always names the port actually in use:
+//! port 0 in the redirect URL selects an ephemeral port (RFC 8252, Section
+//! 7.3), and a configured port range is tried in order until one is free.
+//!
+//! On headless hosts the browser runs elsewhere and the redirect never reaches
+//! the listener. The user then pastes the redirected URL into the terminal or
+//! hands it to the waiting process with `complete-login`
+//! ([`deliver_authorization_response`]).
 
+use super::pages::CallbackPages;
 use crate::error::{ProxyError, Result};
 use axum::{
     extract::Query,
+    http::StatusCode,
     response::{Html, IntoResponse},
     routing::get,
     Router,
 };
 use serde::Deserialize;
-use std::net::SocketAddr;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use tokio::net::TcpListener;
 use tokio::sync::oneshot;
+use url::{Host, Url};
 
 const CALLBACK_TIMEOUT_SECS: u64 = 300;
 
+/// How long the browser waits for the token exchange to finish
+const OUTCOME_TIMEOUT_SECS: u64 = 60;
+
+/// How long to wait for the final page to be sent
+const RESPONSE_FLUSH_TIMEOUT_SECS: u64 = 5;
+
+/// Port used when the redirect URL does not name one
+const DEFAULT_CALLBACK_PORT: u16 = 8080;
+
 #[derive(Debug, Deserialize)]
 pub struct CallbackQuery {
     pub code: Option<String>,
     pub state: Option<String>,
     pub error: Option<String>,
     pub error_description: Option<String>,
+    pub iss: Option<String>,
 }
 
 pub struct CallbackResult {
     pub code: String,
     pub state: String,
+    pub iss: Option<String>,
 }
 
-/// Run OAuth callback server and wait for authorization code
-pub async fn run_callback_server(port: u16, path: &str) -> Result<CallbackResult> {
-    let (tx, rx) = oneshot::channel::<Result<CallbackResult>>();
+impl CallbackQuery {
+    /// Turn the authorization response into a result (errors first, then code and state)
+    pub fn into_result(self) -> Result<CallbackResult> {
+        if let Some(error) = self.error {
+            let description = self
+                .error_description
+                .unwrap_or_else(|| "No description provided".to_string());
 
-    // Wrap sender in Arc<Mutex> so it can be shared with the handler
-    let tx = std::sync::Arc::new(tokio::sync::Mutex::new(Some(tx)));
+            return Err(ProxyError::Callback(format!(
+                "OAuth error: {} - {}",
+                error, description
+            )));
+        }
 
-    // Create the callback handler
-    let callback_path = path.to_string();
-    let app =
-        Router::new().route(
-            &callback_path,
-            get({
-                let tx = tx.clone();
-                move |Query(params): Query<CallbackQuery>| async move {
-                    handle_callback(params, tx).await
-                }
+        match (self.code, self.state) {
+            (Some(code), Some(state)) => Ok(CallbackResult {
+                code,
+                state,
+                iss: self.iss,
             }),
-        );
+            _ => Err(ProxyError::Callback(
+                "Missing code or state parameter in callback".to_string(),
+            )),
+        }
+    }
+}
+
+/// Parse an authorization response pasted by the user
+///
+/// Accepts the full redirected URL, its query string, or just the code.
+pub fn parse_authorization_response(input: &str) -> Result<CallbackQuery> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(ProxyError::Callback(
+            "Empty authorization response".to_string(),
+        ));
+    }
+
+    let query = match Url::parse(input) {
+        Ok(url) => url.query().unwrap_or_default().to_string(),
+        Err(_) if input.contains('=') => input.trim_start_matches('?').to_string(),
+        Err(_) => {
+            return Ok(CallbackQuery {
+                code: Some(input.to_string()),
+                state: None,
+                error: None,
+                error_description: None,
+                iss: None,
+            })
+        }
+    };
+
+    let mut params = CallbackQuery {
+        code: None,
+        state: None,
+        error: None,
+        error_description: None,
+        iss: None,
+    };
+    for (name, value) in url::form_urlencoded::parse(query.as_bytes()) {
+        let value = Some(value.into_owned());
+        match name.as_ref() {
+            "code" => params.code = value,
+            "state" => params.state = value,
+            "error" => params.error = value,
+            "error_description" => params.error_description = value,
+            "iss" => params.iss = value,
+            _ => {}
+        }
+    }
+
+    if params.code.is_none() && params.error.is_none() {
+        return Err(ProxyError::Callback(
+            "No code or error parameter found in the pasted URL".to_string(),
+        ));
+    }
+
+    Ok(params)
+}
+
+/// Hand a redirected URL to the login waiting on this machine (`complete-login`)
+///
+/// The PKCE verifier only exists in the process that started the flow, so the
+/// response is replayed against its callback listener instead of redeemed here.
+pub async fn deliver_authorization_response(redirected_url: &str) -> Result<()> {
+    let mut url = Url::parse(redirected_url.trim()).map_err(|e| {
+        ProxyError::Callback(format!(
+            "Expected the full redirected URL (http://localhost:<port>/...): {}",
+            e
+        ))
+    })?;
+
+    match url.host() {
+        Some(Host::Domain("localhost")) => {
+            url.set_host(Some("127.0.0.1"))?;
+        }
+        Some(Host::Ipv4(ip)) if ip.is_loopback() => {}
+        Some(Host::Ipv6(ip)) if ip.is_loopback() => {}
+        _ => {
+            return Err(ProxyError::Callback(format!(
+                "{} is not a loopback redirect URL",
+                redirected_url
+            )))
+        }
+    }
+    parse_authorization_response(url.as_str())?;
+
+    let client = reqwest::Client::builder().no_proxy().build()?;
+    let response = client.get(url.clone()).send().await.map_err(|e| {
+        if e.is_connect() {
+            ProxyError::Callback(format!(
+                "No login is waiting on port {}. Start `login` (or the proxy) on this machine \
+                 first and complete the sign-in it prints",
+                url.port_or_known_default().unwrap_or_default()
+            ))
+        } else {
+            ProxyError::Http(e)
+        }
+    })?;
+
+    if !response.status().is_success() {
+        return Err(ProxyError::Callback(format!(
+            "The waiting login rejected the authorization response (status {})",
+            response.status()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Parse a callback port specification such as `8080-8090` or `8080,9000-9005`
+pub fn parse_port_range(spec: &str) -> Result<Vec<u16>> {
+    let invalid = |part: &str| {
+        ProxyError::Config(format!(
+            "Invalid callback port range '{}': expected ports or ranges like 8080-8090, got '{}'",
+            spec, part
+        ))
+    };
+
+    let mut ports = Vec::new();
+    for part in spec.split(',').map(str::trim) {
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (start.trim(), end.trim()),
+            None => (part, part),
+        };
+        let start: u16 = start.parse().map_err(|_| invalid(part))?;
+        let end: u16 = end.parse().map_err(|_| invalid(part))?;
+        if start == 0 || start > end {
+            return Err(invalid(part));
+        }
+
+        for port in start..=end {
+            if !ports.contains(&port) {
+                ports.push(port);
+            }
+        }
+    }
+
+    Ok(ports)
+}
+
+/// Callback listener bound to a local port
+pub struct CallbackListener {
+    listener: TcpListener,
+    redirect_uri: String,
+    path: String,
+}
+
+impl CallbackListener {
+    /// Bind the callback listener for a redirect URL
+    ///
+    /// With `ports`, each port is tried in order instead of the one in the
+    /// redirect URL. Port 0 (in the URL) binds an ephemeral port.
+    pub async fn bind(redirect_url: &str, ports: Option<&[u16]>) -> Result<Self> {
+        let mut redirect_uri = Url::parse(redirect_url)?;
+
+        let ip = match redirect_uri.host() {
+            Some(Host::Ipv4(ip)) => IpAddr::V4(ip),
+            Some(Host::Ipv6(ip)) => IpAddr::V6(ip),
+            _ => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        };
+
+        let candidates = match ports {
+            Some(ports) if !ports.is_empty() => ports.to_vec(),
+            _ => vec![redirect_uri.port().unwrap_or(DEFAULT_CALLBACK_PORT)],
+        };
+
+        let mut failures = Vec::new();
+        for port in candidates {
+            match TcpListener::bind(SocketAddr::new(ip, port)).await {
+                Ok(listener) => {
+                    let port = listener.local_addr()?.port();
+                    redirect_uri.set_port(Some(port)).map_err(|_| {
+                        ProxyError::Config(format!("Invalid redirect URL: {}", redirect_url))
+                    })?;
+
+                    return Ok(Self {
+                        listener,
+                        path: redirect_uri.path().to_string(),
+                        redirect_uri: redirect_uri.to_string(),
+                    });
+                }
+                Err(e) => {
+                    tracing::debug!("Callback port {} unavailable: {}", port, e);
+                    failures.push(format!("{} ({})", port, e));
+                }
+            }
+        }
+
+        Err(ProxyError::Callback(format!(
+            "No usable callback port on {}: tried {}. Stop the process using the port, \
+             configure other ports with --oidc-callback-ports (e.g. 8080-8090), or use an \
+             ephemeral port with a redirect URL like http://127.0.0.1:0{} if your provider \
+             allows any loopback port",
+            ip,
+            failures.join(", "),
+            redirect_uri.path()
+        )))
+    }
+
+    /// Redirect URI naming the bound port, to be sent to the provider
+    pub fn redirect_uri(&self) -> &str {
+        &self.redirect_uri
+    }
+}
+
+/// Outcome of the login, shown to the browser that delivered the callback
+enum LoginOutcome {
+    Success { user: Option<String> },
+    Failure(String),
+}
 
-    // Bind to localhost on the specified port
-    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+/// Answers the browser once the authorization response has been processed
+///
+/// The browser's request is held open until [`CallbackResponder::success`] or
+/// [`CallbackResponder::failure`] is called, so the page reflects the outcome
+/// of the token exchange. Dropping the responder shows a generic error page.
+pub struct CallbackResponder {
+    outcome: Option<oneshot::Sender<LoginOutcome>>,
+    server: tokio::task::JoinHandle<()>,
+}
+
+impl CallbackResponder {
+    /// Show the success page (optionally naming the signed-in user)
+    pub async fn success(self, user: Option<String>) {
+        self.finish(LoginOutcome::Success { user }).await
+    }
+
+    /// Show the error page
+    pub async fn failure(self, error: &str) {
+        self.finish(LoginOutcome::Failure(error.to_string())).await
+    }
 
-    tracing::info!(
-        "OAuth callback server listening on http://{}{}",
-        addr,
-        callback_path
+    async fn finish(mut self, outcome: LoginOutcome) {
+        if let Some(sender) = self.outcome.take() {
+            let _ = sender.send(outcome);
+        }
+
+        // Let the page reach the browser before the process may exit
+        let _ = tokio::time::timeout(
+            tokio::time::Duration::from_secs(RESPONSE_FLUSH_TIMEOUT_SECS),
+            &mut self.server,
+        )
+        .await;
+    }
+}
+
+type CallbackSender = std::sync::Arc<
+    tokio::sync::Mutex<Option<oneshot::Sender<(CallbackResult, oneshot::Sender<LoginOutcome>)>>>,
+>;
+
+/// Run OAuth callback server and wait for authorization code
+///
+/// Error responses from the provider are answered and returned as errors;
+/// successful ones are returned with a responder for the final page.
+pub async fn run_callback_server(
+    listener: CallbackListener,
+    pages: CallbackPages,
+) -> Result<(CallbackResult, CallbackResponder)> {
+    let CallbackListener {
+        listener,
+        redirect_uri,
+        path,
+    } = listener;
+
+    let (tx, rx) = oneshot::channel();
+    let (error_tx, error_rx) = oneshot::channel::<ProxyError>();
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+    // Wrap senders in Arc<Mutex> so they can be shared with the handler
+    let tx: CallbackSender = std::sync::Arc::new(tokio::sync::Mutex::new(Some(tx)));
+    let error_tx = std::sync::Arc::new(tokio::sync::Mutex::new(Some(error_tx)));
+    let pages = std::sync::Arc::new(pages);
+
+    // Create the callback handler
+    let app = Router::new().route(
+        &path,
+        get(move |Query(params): Query<CallbackQuery>| async move {
+            handle_callback(params, tx, error_tx, pages).await
+        }),
     );
 
-    // Start server with graceful shutdown
-    let server = axum::serve(tokio::net::TcpListener::bind(addr).await?, app);
+    tracing::info!("OAuth callback server listening on {}", redirect_uri);
+
+    // Serve in the background until the callback arrived; in-flight responses
+    // are completed during graceful shutdown
+    let mut server = tokio::spawn(async move {
+        let shutdown = async {
+            let _ = shutdown_rx.await;
+        };
+        if let Err(e) = axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown)
+            .await
+        {
+            tracing::warn!("OAuth callback server failed: {}", e);
+        }
+    });
 
-    // Run server in background and wait for callback with timeout
-    tokio::select! {
+    // Wait for callback with timeout
+    let result = tokio::select! {
         result = rx => {
-            result.map_err(|_| ProxyError::Callback("Callback channel closed".to_string()))?
+            result.map_err(|_| ProxyError::Callback("Callback channel closed".to_string()))
+        }
+        error = error_rx => {
+            Err(error.unwrap_or_else(|_| ProxyError::Callback("Callback channel closed".to_string())))
         }
         _ = tokio::time::sleep(tokio::time::Duration::from_secs(CALLBACK_TIMEOUT_SECS)) => {
             Err(ProxyError::Timeout(format!(
@@ -72,90 +386,221 @@ pub async fn run_callback_server(port: u16, path: &str) -> Result<CallbackResult
                 CALLBACK_TIMEOUT_SECS
             )))
         }
-        server_result = server => {
-            server_result?;
+        _ = &mut server => {
             Err(ProxyError::Callback("Server stopped unexpectedly".to_string()))
         }
+    };
+
+    let _ = shutdown_tx.send(());
+
+    match result {
+        Ok((result, outcome)) => Ok((
+            result,
+            CallbackResponder {
+                outcome: Some(outcome),
+                server,
+            },
+        )),
+        Err(e) => {
+            // Deliver the error page before returning
+            let _ = tokio::time::timeout(
+                tokio::time::Duration::from_secs(RESPONSE_FLUSH_TIMEOUT_SECS),
+                server,
+            )
+            .await;
+            Err(e)
+        }
     }
 }
 
 async fn handle_callback(
     params: CallbackQuery,
-    tx: std::sync::Arc<tokio::sync::Mutex<Option<oneshot::Sender<Result<CallbackResult>>>>>,
+    tx: CallbackSender,
+    error_tx: std::sync::Arc<tokio::sync::Mutex<Option<oneshot::Sender<ProxyError>>>>,
+    pages: std::sync::Arc<CallbackPages>,
 ) -> impl IntoResponse {
-    // Check for errors first
-    if let Some(error) = params.error {
-        let description = params
-            .error_description
-            .unwrap_or_else(|| "No description provided".to_string());
-
-        let error_msg = format!("OAuth error: {} - {}", error, description);
-
-        // Send error through channel
-        if let Some(sender) = tx.lock().await.take() {
-            let _ = sender.send(Err(ProxyError::Callback(error_msg.clone())));
-        }
-
-        return Html(format!(
-            r#"
-            <html>
-                <head><title>Authentication Failed</title></head>
-                <body>
-                    <h1>Authentication Failed</h1>
-                    <p>{}</p>
-                    <p>You can close this window.</p>
-                </body>
-            </html>
-            "#,
-            error_msg
-        ));
-    }
-
-    // Extract code and state
-    match (params.code, params.state) {
-        (Some(code), Some(state)) => {
-            let result = CallbackResult { code, state };
+    let result = match params.into_result() {
+        Ok(result) => result,
+        Err(e) => {
+            let page = pages.error(&callback_error_message(&e));
 
-            // Send result through channel
-            if let Some(sender) = tx.lock().await.take() {
-                let _ = sender.send(Ok(result));
+            // Send error through channel
+            if let Some(sender) = error_tx.lock().await.take() {
+                let _ = sender.send(e);
             }
 
-            Html(
-                r#"
-                <html>
-                    <head><title>Authentication Successful</title></head>
-                    <body>
-                        <h1>Authentication Successful!</h1>
-                        <p>You have been successfully authenticated.</p>
-                        <p>You can close this window and return to your application.</p>
-                    </body>
-                </html>
-                "#
-                .to_string(),
-            )
+            return (StatusCode::BAD_REQUEST, Html(page));
         }
-        _ => {
-            let error_msg = "Missing code or state parameter in callback";
+    };
 
-            // Send error through channel
-            if let Some(sender) = tx.lock().await.take() {
-                let _ = sender.send(Err(ProxyError::Callback(error_msg.to_string())));
-            }
+    // Send result through channel
+    let (outcome_tx, outcome_rx) = oneshot::channel();
+    let Some(sender) = tx.lock().await.take() else {
+        return (
+            StatusCode::CONFLICT,
+            Html(pages.error("This sign-in has already been processed")),
+        );
+    };
+    let _ = sender.send((result, outcome_tx));
 
-            Html(format!(
-                r#"
-                <html>
-                    <head><title>Authentication Failed</title></head>
-                    <body>
-                        <h1>Authentication Failed</h1>
-                        <p>{}</p>
-                        <p>You can close this window.</p>
-                    </body>
-                </html>
-                "#,
-                error_msg
-            ))
+    // Hold the response until the token exchange finished
+    let outcome = tokio::time::timeout(
+        tokio::time::Duration::from_secs(OUTCOME_TIMEOUT_SECS),
+        outcome_rx,
+    )
+    .await;
+
+    match outcome {
+        Ok(Ok(LoginOutcome::Success { user })) => {
+            (StatusCode::OK, Html(pages.success(user.as_deref())))
         }
+        Ok(Ok(LoginOutcome::Failure(error))) => {
+            (StatusCode::BAD_REQUEST, Html(pages.error(&error)))
+        }
+        _ => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Html(pages.error("The sign-in could not be completed")),
+        ),
+    }
+}
+
+/// Error text for the browser (without the error type prefix)
+pub fn callback_error_message(error: &ProxyError) -> String {
+    match error {
+        ProxyError::Callback(message) | ProxyError::Auth(message) | ProxyError::Token(message) => {
+            message.clone()
+        }
+        e => e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_port_range() {
+        assert_eq!(parse_port_range("8080").unwrap(), vec![8080]);
+        assert_eq!(
+            parse_port_range("8080-8082, 9000,8081").unwrap(),
+            vec![8080, 8081, 8082, 9000]
+        );
+        assert!(parse_port_range("8090-8080").is_err());
+        assert!(parse_port_range("0-10").is_err());
+        assert!(parse_port_range("http").is_err());
+    }
+
+    #[test]
+    fn test_parse_authorization_response() {
+        let params = parse_authorization_response(
+            "  http://localhost:8080/auth/callback?code=abc%2F1&state=xyz&iss=https%3A%2F%2Fauth.example.com\n",
+        )
+        .unwrap();
+        assert_eq!(params.code.as_deref(), Some("abc/1"));
+        assert_eq!(params.state.as_deref(), Some("xyz"));
+        assert_eq!(params.iss.as_deref(), Some("https://auth.example.com"));
+
+        let params = parse_authorization_response("?code=abc&state=xyz").unwrap();
+        assert_eq!(params.code.as_deref(), Some("abc"));
+
+        let params = parse_authorization_response("abc").unwrap();
+        assert_eq!(params.code.as_deref(), Some("abc"));
+        assert!(params.state.is_none());
+
+        let err = parse_authorization_response("http://localhost:8080/cb?error=access_denied")
+            .unwrap()
+            .into_result()
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("access_denied"));
+
+        assert!(parse_authorization_response("http://localhost:8080/cb").is_err());
+        assert!(parse_authorization_response("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_deliver_authorization_response() {
+        let listener = CallbackListener::bind("http://localhost:0/auth/callback", None)
+            .await
+            .unwrap();
+        let redirected = format!("{}?code=abc&state=xyz", listener.redirect_uri());
+
+        let delivered =
+            tokio::spawn(async move { deliver_authorization_response(&redirected).await });
+        let (result, responder) =
+            run_callback_server(listener, CallbackPages::new("https://auth.example.com"))
+                .await
+                .unwrap();
+        assert_eq!(result.code, "abc");
+        assert_eq!(result.state, "xyz");
+
+        responder.success(Some("jane".to_string())).await;
+        delivered.await.unwrap().unwrap();
+
+        // A failed token exchange is reported to complete-login as well
+        let listener = CallbackListener::bind("http://127.0.0.1:0/auth/callback", None)
+            .await
+            .unwrap();
+        let redirected = format!("{}?code=abc&state=xyz", listener.redirect_uri());
+        let delivered =
+            tokio::spawn(async move { deliver_authorization_response(&redirected).await });
+        let (_, responder) = run_callback_server(listener, CallbackPages::default())
+            .await
+            .unwrap();
+        responder.failure("invalid_grant").await;
+        assert!(delivered.await.unwrap().is_err());
+
+        assert!(
+            deliver_authorization_response("https://example.com/cb?code=abc&state=xyz")
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_ephemeral_port() {
+        let listener = CallbackListener::bind("http://127.0.0.1:0/auth/callback", None)
+            .await
+            .unwrap();
+
+        let port = listener.listener.local_addr().unwrap().port();
+        assert_ne!(port, 0);
+        assert_eq!(
+            listener.redirect_uri(),
+            format!("http://127.0.0.1:{}/auth/callback", port)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_falls_back_to_next_port() {
+        let busy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let busy_port = busy.local_addr().unwrap().port();
+
+        // Find a free port to fall back to
+        let free_port = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let listener = CallbackListener::bind(
+            "http://localhost:8080/auth/callback",
+            Some(&[busy_port, free_port]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            listener.redirect_uri(),
+            format!("http://localhost:{}/auth/callback", free_port)
+        );
+
+        let err = CallbackListener::bind("http://localhost/cb", Some(&[busy_port]))
+            .await
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains(&format!("tried {} (", busy_port)));
+        assert!(err.contains("http://127.0.0.1:0/cb"));
     }
 }
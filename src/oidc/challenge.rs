@@ -0,0 +1,185 @@
+//! `WWW-Authenticate` challenges of protected resources
+//!
+//! Parses `Bearer` / `DPoP` challenges (RFC 6750, RFC 9449) so the proxy can
+//! react to them:
+//!
+//! - `insufficient_scope` (403): re-authorize with the required `scope`
+//! - `insufficient_user_authentication` (401, RFC 9470): re-authorize with
+//!   the required `acr_values` / `max_age` (step-up MFA)
+
+use reqwest::header::{HeaderMap, WWW_AUTHENTICATE};
+
+/// Error code for tokens lacking a required scope
+pub const INSUFFICIENT_SCOPE: &str = "insufficient_scope";
+
+/// Error code for tokens from a too weak or too old authentication (RFC 9470)
+pub const INSUFFICIENT_USER_AUTHENTICATION: &str = "insufficient_user_authentication";
+
+/// Parameters of a `Bearer` or `DPoP` challenge
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuthChallenge {
+    pub scheme: String,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+    pub scope: Option<String>,
+    pub acr_values: Option<String>,
+    pub max_age: Option<u64>,
+}
+
+impl AuthChallenge {
+    /// First `Bearer` or `DPoP` challenge carrying an error, if any
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        headers
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(parse_challenges)
+            .filter(|challenge| {
+                challenge.scheme.eq_ignore_ascii_case("Bearer")
+                    || challenge.scheme.eq_ignore_ascii_case("DPoP")
+            })
+            .find(|challenge| challenge.error.is_some())
+    }
+
+    /// Whether the challenge asks for re-authorization (more scopes or step-up)
+    pub fn requires_step_up(&self) -> bool {
+        matches!(
+            self.error.as_deref(),
+            Some(INSUFFICIENT_SCOPE | INSUFFICIENT_USER_AUTHENTICATION)
+        )
+    }
+
+    /// Scopes required by the resource
+    pub fn scopes(&self) -> Vec<String> {
+        self.scope
+            .as_deref()
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(String::from)
+            .collect()
+    }
+}
+
+/// Parse a header value into its challenges (`scheme param=value, ...`)
+///
+/// A header may carry several challenges; a token followed by another token
+/// (rather than `=`) starts a new challenge.
+fn parse_challenges(value: &str) -> Vec<AuthChallenge> {
+    let mut challenges = Vec::new();
+    let mut current: Option<AuthChallenge> = None;
+    let mut rest = value.trim();
+
+    while !rest.is_empty() {
+        rest = rest.trim_start_matches([',', ' ', '\t']);
+        let end = rest
+            .find(|c: char| c == '=' || c == ',' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let token = &rest[..end];
+        rest = rest[end..].trim_start();
+
+        if token.is_empty() {
+            break;
+        }
+
+        if let Some(after_eq) = rest.strip_prefix('=') {
+            let (value, remainder) = parse_value(after_eq.trim_start());
+            rest = remainder;
+            if let Some(ref mut challenge) = current {
+                set_param(challenge, token, value);
+            }
+        } else {
+            challenges.extend(current.take());
+            current = Some(AuthChallenge {
+                scheme: token.to_string(),
+                ..Default::default()
+            });
+        }
+    }
+
+    challenges.extend(current);
+    challenges
+}
+
+/// Parse a quoted string or token value, returning it and the remaining input
+fn parse_value(input: &str) -> (String, &str) {
+    if let Some(quoted) = input.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    if let Some((_, escaped)) = chars.next() {
+                        value.push(escaped);
+                    }
+                }
+                '"' => return (value, &quoted[i + 1..]),
+                c => value.push(c),
+            }
+        }
+        (value, "")
+    } else {
+        let end = input.find(',').unwrap_or(input.len());
+        (input[..end].trim().to_string(), &input[end..])
+    }
+}
+
+fn set_param(challenge: &mut AuthChallenge, name: &str, value: String) {
+    match name.to_ascii_lowercase().as_str() {
+        "error" => challenge.error = Some(value),
+        "error_description" => challenge.error_description = Some(value),
+        "scope" => challenge.scope = Some(value),
+        "acr_values" => challenge.acr_values = Some(value),
+        "max_age" => challenge.max_age = value.parse().ok(),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(WWW_AUTHENTICATE, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_parse_insufficient_scope() {
+        let challenge = AuthChallenge::from_headers(&headers(&[
+            r#"Bearer realm="mcp", error="insufficient_scope", error_description="Needs \"write\"", scope="mcp:read mcp:write""#,
+        ]))
+        .unwrap();
+
+        assert_eq!(challenge.scheme, "Bearer");
+        assert_eq!(challenge.error.as_deref(), Some(INSUFFICIENT_SCOPE));
+        assert_eq!(
+            challenge.error_description.as_deref(),
+            Some(r#"Needs "write""#)
+        );
+        assert_eq!(challenge.scopes(), vec!["mcp:read", "mcp:write"]);
+        assert!(challenge.requires_step_up());
+    }
+
+    #[test]
+    fn test_parse_multiple_challenges_and_step_up_params() {
+        let challenge = AuthChallenge::from_headers(&headers(&[
+            r#"Basic realm="legacy""#,
+            r#"Newauth realm="apps", DPoP algs="ES256", error=insufficient_user_authentication, acr_values="urn:mfa", max_age=300"#,
+        ]))
+        .unwrap();
+
+        assert_eq!(challenge.scheme, "DPoP");
+        assert_eq!(challenge.acr_values.as_deref(), Some("urn:mfa"));
+        assert_eq!(challenge.max_age, Some(300));
+        assert!(challenge.scopes().is_empty());
+        assert!(challenge.requires_step_up());
+
+        // Challenges without an error are not actionable
+        assert!(AuthChallenge::from_headers(&headers(&[r#"Bearer realm="mcp""#])).is_none());
+        let invalid = AuthChallenge::from_headers(&headers(&[r#"Bearer error="invalid_token""#]));
+        assert!(!invalid.unwrap().requires_step_up());
+    }
+}
@@ -1,23 +1,45 @@
 //! OIDC client implementation
 //!
 //! Main OIDC client that orchestrates the OAuth 2.0 authorization code flow with PKCE.
-//! Manages token lifecycle (cache, refresh, re-authentication).
+//! Manages token lifecycle (cache, refresh, re-authentication) and, if
+//! configured, the exchange of the login token for a backend token (RFC 8693).
 
-use super::{callback, OidcConfig, PkceParams, TokenInfo, TokenResponse};
+use super::challenge::AuthChallenge;
+use super::dpop::{self, Dpop, DpopKey};
+use super::exchange::TokenExchange;
+use super::introspection::IntrospectionResponse;
+use super::pages::CallbackPages;
+use super::userinfo::{self, UserIdentity};
+use super::{callback, jwt, OidcConfig, PkceParams, TokenCacheKey, TokenInfo, TokenResponse};
+use crate::config::{Config, ParMode};
 use crate::error::{ProxyError, Result};
+use crate::http::{HttpClientFactory, Target};
+use serde_json::{Map, Value};
+use std::io::IsTerminal;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 use url::Url;
 
 /// OIDC client for managing OAuth 2.0 authentication
 pub struct OidcClient {
-    issuer_url: String,
     client_id: String,
     client_secret: Option<String>,
-    scopes: Vec<String>,
+    scopes: RwLock<Vec<String>>,
     redirect_url: String,
+    callback_ports: Option<Vec<u16>>,
+    open_browser: bool,
+    par: ParMode,
+    auth_params: Vec<(String, String)>,
+    dpop: Option<Arc<Dpop>>,
+    pages: CallbackPages,
+    http: reqwest::Client,
     oidc_config: OidcConfig,
+    cache_key: TokenCacheKey,
     token_info: Arc<RwLock<Option<TokenInfo>>>,
+    exchange: Option<TokenExchange>,
+    exchanged_token_info: RwLock<Option<TokenInfo>>,
+    /// Held while renewing tokens, so concurrent requests renew only once
+    auth_lock: Mutex<()>,
 }
 
 impl OidcClient {
@@ -29,25 +51,130 @@ impl OidcClient {
         scopes: Vec<String>,
         redirect_url: String,
     ) -> Result<Self> {
+        let cache_key = TokenCacheKey::new(&issuer_url, &client_id, &scopes, None);
+
+        Self::with_cache_key(
+            issuer_url,
+            client_id,
+            client_secret,
+            scopes,
+            redirect_url,
+            cache_key,
+            &HttpClientFactory::default(),
+        )
+        .await
+    }
+
+    /// Create a new OIDC client from the proxy configuration
+    ///
+    /// Tokens are cached per backend and profile (see [`TokenCacheKey`]).
+    pub async fn from_config(config: &Config) -> Result<Self> {
+        let mut client = Self::with_cache_key(
+            config.oidc_issuer_url.clone(),
+            config.oidc_client_id.clone(),
+            config.client_secret().map(String::from),
+            config.scopes(),
+            config.redirect_url(),
+            TokenCacheKey::from_config(config),
+            &HttpClientFactory::from_config(config)?,
+        )
+        .await?;
+        client.callback_ports = config.callback_ports()?;
+        client.open_browser = !config.no_browser;
+        client.par = config.oidc_par;
+        client.auth_params = config.authorization_params()?;
+        if config.dpop {
+            let key = DpopKey::load_or_generate(config.profile.as_deref())?;
+            client.dpop = Some(Arc::new(Dpop::new(key)));
+        }
+        client.pages = CallbackPages::from_config(config, &client.oidc_config.issuer)?;
+        // Learn the expiry of cached opaque tokens that were not introspected yet
+        let cached = client.token_info.read().await.clone();
+        if let Some(mut tokens) = cached.filter(TokenInfo::needs_introspection) {
+            client.introspect_if_opaque(&mut tokens).await;
+            tokens.save_to_disk(&client.cache_key)?;
+            *client.token_info.write().await = Some(tokens);
+        }
+
+        if let Some(exchange) = TokenExchange::from_config(config) {
+            let exchanged = TokenInfo::load_from_disk(&exchange.cache_key(&client.cache_key))?;
+            client.exchanged_token_info = RwLock::new(exchanged);
+            client.exchange = Some(exchange);
+        }
+
+        Ok(client)
+    }
+
+    async fn with_cache_key(
+        issuer_url: String,
+        client_id: String,
+        client_secret: Option<String>,
+        scopes: Vec<String>,
+        redirect_url: String,
+        cache_key: TokenCacheKey,
+        http: &HttpClientFactory,
+    ) -> Result<Self> {
+        let http_client = http.client(Target::Provider)?;
+
         // Discover OIDC configuration
-        let oidc_config = OidcConfig::discover(&issuer_url).await?;
+        let mut oidc_config = OidcConfig::discover(&http_client, &issuer_url).await?;
+        if http.has_client_certificate() {
+            oidc_config.use_mtls_endpoint_aliases();
+        }
 
         // Try to load cached tokens
-        let token_info = TokenInfo::load_from_disk(&issuer_url)?;
+        let token_info = TokenInfo::load_from_disk(&cache_key)?;
+
+        // Keep scopes granted by an earlier step-up when re-authorizing
+        let mut scopes = scopes;
+        if let Some(granted) = token_info.as_ref().and_then(|t| t.scope.as_deref()) {
+            merge_scopes(&mut scopes, granted.split_whitespace().map(String::from));
+        }
 
         Ok(Self {
-            issuer_url,
             client_id,
             client_secret,
-            scopes,
+            scopes: RwLock::new(scopes),
             redirect_url,
+            callback_ports: None,
+            open_browser: true,
+            par: ParMode::default(),
+            auth_params: Vec::new(),
+            dpop: None,
+            pages: CallbackPages::new(&oidc_config.issuer),
+            http: http_client,
             oidc_config,
+            cache_key,
             token_info: Arc::new(RwLock::new(token_info)),
+            exchange: None,
+            exchanged_token_info: RwLock::new(None),
+            auth_lock: Mutex::new(()),
         })
     }
 
-    /// Get a valid access token (cached, refreshed, or newly authenticated)
+    /// Get a valid access token for the backend (cached, refreshed, or newly authenticated)
+    ///
+    /// With token exchange this is the exchanged token, which is exchanged
+    /// again once expired.
     pub async fn get_token(&self) -> Result<String> {
+        if let Some(token) = self.valid_backend_token(None).await {
+            return Ok(token);
+        }
+
+        // Another request may have renewed the token while we waited
+        let _guard = self.auth_lock.lock().await;
+        if let Some(token) = self.valid_backend_token(None).await {
+            return Ok(token);
+        }
+
+        let subject_token = self.get_subject_token().await?;
+        self.backend_token(subject_token).await
+    }
+
+    /// Get a valid login token (cached, refreshed, or newly authenticated)
+    ///
+    /// Called with `auth_lock` held.
+    async fn get_subject_token(&self) -> Result<String> {
         // Check if we have a valid cached token
         {
             let token_guard = self.token_info.read().await;
@@ -59,11 +186,94 @@ impl OidcClient {
         }
 
         // Token expired or missing - try to renew
-        self.renew_token().await
+        self.renew_subject_token().await
+    }
+
+    /// Cached backend token if still valid and not the rejected one
+    async fn valid_backend_token(&self, rejected_token: Option<&str>) -> Option<String> {
+        let token_guard = self.backend_token_info().read().await;
+        token_guard
+            .as_ref()
+            .filter(|token| token.is_valid() && Some(token.access_token.as_str()) != rejected_token)
+            .map(|token| token.access_token.clone())
     }
 
-    /// Renew token (refresh or full auth flow)
-    pub async fn renew_token(&self) -> Result<String> {
+    /// Perform the interactive login flow, replacing any cached tokens
+    pub async fn login(&self) -> Result<()> {
+        let _guard = self.auth_lock.lock().await;
+        self.perform_auth_flow(&[]).await.map(|_| ())
+    }
+
+    /// Re-authorize for a step-up challenge of the backend
+    ///
+    /// Requests the union of the current and the required scopes, with the
+    /// required `acr_values` / `max_age`, and replaces the cached tokens.
+    /// `rejected_token` is the token the backend refused: if another request
+    /// has re-authorized in the meantime, its token is returned instead.
+    pub async fn step_up(&self, challenge: &AuthChallenge, rejected_token: &str) -> Result<String> {
+        let _guard = self.auth_lock.lock().await;
+        if let Some(token) = self.valid_backend_token(Some(rejected_token)).await {
+            return Ok(token);
+        }
+
+        let scope = {
+            let mut scopes = self.scopes.write().await;
+            merge_scopes(&mut scopes, challenge.scopes());
+            scopes.join(" ")
+        };
+        tracing::info!(
+            "Backend requires step-up authorization ({}), re-authorizing with scopes '{}'",
+            challenge.error.as_deref().unwrap_or_default(),
+            scope
+        );
+
+        let max_age = challenge.max_age.map(|max_age| max_age.to_string());
+        let mut params = Vec::new();
+        if let Some(ref acr_values) = challenge.acr_values {
+            params.push(("acr_values", acr_values.as_str()));
+        }
+        if let Some(ref max_age) = max_age {
+            params.push(("max_age", max_age.as_str()));
+        }
+
+        let subject_token = self.perform_auth_flow(&params).await?;
+        self.backend_token(subject_token).await
+    }
+
+    /// Turn a login token into the token for the backend (exchanging it if configured)
+    async fn backend_token(&self, subject_token: String) -> Result<String> {
+        match self.exchange {
+            Some(ref exchange) => self.exchange_token(exchange, &subject_token).await,
+            None => Ok(subject_token),
+        }
+    }
+
+    /// Tokens sent to the backend: the exchanged ones with token exchange
+    fn backend_token_info(&self) -> &RwLock<Option<TokenInfo>> {
+        match self.exchange {
+            Some(_) => &self.exchanged_token_info,
+            None => &self.token_info,
+        }
+    }
+
+    /// Renew the backend token (refresh or full auth flow, then exchange)
+    ///
+    /// `rejected_token` is the token the backend refused: if another request
+    /// has renewed it in the meantime, the new token is returned instead.
+    pub async fn renew_token(&self, rejected_token: &str) -> Result<String> {
+        let _guard = self.auth_lock.lock().await;
+        if let Some(token) = self.valid_backend_token(Some(rejected_token)).await {
+            return Ok(token);
+        }
+
+        let subject_token = self.renew_subject_token().await?;
+        self.backend_token(subject_token).await
+    }
+
+    /// Renew the login token (refresh or full auth flow)
+    ///
+    /// Called with `auth_lock` held.
+    async fn renew_subject_token(&self) -> Result<String> {
         // Check if we can refresh
         let can_refresh = {
             let token_guard = self.token_info.read().await;
@@ -83,50 +293,76 @@ impl OidcClient {
         }
 
         // Fall back to full auth flow
-        self.perform_auth_flow().await
+        self.perform_auth_flow(&[]).await
     }
 
     /// Perform full OAuth 2.0 authorization code flow with PKCE
-    async fn perform_auth_flow(&self) -> Result<String> {
+    ///
+    /// `extra_params` are added to the authorization request, replacing
+    /// configured parameters of the same name.
+    async fn perform_auth_flow(&self, extra_params: &[(&str, &str)]) -> Result<String> {
         tracing::info!("Starting OAuth 2.0 authorization code flow with PKCE");
 
         // Generate PKCE parameters and state
         let pkce = PkceParams::generate();
         let state = generate_state();
 
+        // Bind the callback listener first so the redirect URI names the actual port
+        let listener =
+            callback::CallbackListener::bind(&self.redirect_url, self.callback_ports.as_deref())
+                .await?;
+        let redirect_uri = listener.redirect_uri().to_string();
+
         // Build authorization URL
-        let auth_url = self.build_authorization_url(&state, &pkce)?;
+        let auth_url = self
+            .build_authorization_url(&state, &pkce, &redirect_uri, extra_params)
+            .await?;
 
         // Open browser
-        tracing::info!("Opening browser for authorization: {}", auth_url);
-        if let Err(e) = webbrowser::open(&auth_url) {
-            tracing::warn!("Failed to open browser: {}", e);
-            tracing::warn!("Could not open browser automatically.");
-            tracing::warn!("Please open this URL in your browser: {}", auth_url);
-        }
-
-        // Extract port and path from redirect URL
-        let redirect_uri = Url::parse(&self.redirect_url)?;
-        let port = redirect_uri.port().unwrap_or(8080);
-        let path = redirect_uri.path();
+        let browser_opened = self.open_browser && {
+            tracing::info!("Opening browser for authorization: {}", auth_url);
+            match webbrowser::open(&auth_url) {
+                Ok(()) => true,
+                Err(e) => {
+                    tracing::warn!("Failed to open browser: {}", e);
+                    false
+                }
+            }
+        };
 
         // Run callback server and wait for authorization code
-        let callback_result = callback::run_callback_server(port, path).await?;
+        let (callback_result, responder) = if browser_opened {
+            let (result, responder) =
+                callback::run_callback_server(listener, self.pages.clone()).await?;
+            (result, Some(responder))
+        } else {
+            wait_for_manual_response(
+                listener,
+                self.pages.clone(),
+                &auth_url,
+                &state,
+                &self.oidc_config.issuer,
+            )
+            .await?
+        };
 
-        // Validate state to prevent CSRF attacks
-        if callback_result.state != state {
-            return Err(ProxyError::Auth(
-                "State mismatch - possible CSRF attack".to_string(),
-            ));
-        }
+        let result = self
+            .complete_auth_flow(callback_result, &state, &pkce, &redirect_uri)
+            .await;
 
-        // Exchange authorization code for tokens
-        let tokens = self
-            .exchange_code_for_tokens(&callback_result.code, &pkce)
-            .await?;
+        // Show the outcome in the browser
+        if let Some(responder) = responder {
+            match result {
+                Ok(ref tokens) => responder.success(tokens.user()).await,
+                Err(ref e) => {
+                    responder
+                        .failure(&callback::callback_error_message(e))
+                        .await
+                }
+            }
+        }
 
-        // Save and cache tokens
-        tokens.save_to_disk(&self.issuer_url)?;
+        let tokens = result?;
         let access_token = tokens.access_token.clone();
 
         {
@@ -138,6 +374,35 @@ impl OidcClient {
         Ok(access_token)
     }
 
+    /// Validate the authorization response, redeem the code and save the tokens
+    async fn complete_auth_flow(
+        &self,
+        callback_result: callback::CallbackResult,
+        state: &str,
+        pkce: &PkceParams,
+        redirect_uri: &str,
+    ) -> Result<TokenInfo> {
+        // Validate state to prevent CSRF attacks
+        if callback_result.state != state {
+            return Err(ProxyError::Auth(
+                "State mismatch - possible CSRF attack".to_string(),
+            ));
+        }
+
+        // Validate the issuer to prevent mix-up attacks (RFC 9207)
+        self.oidc_config
+            .validate_authorization_response_issuer(callback_result.iss.as_deref())?;
+
+        // Exchange authorization code for tokens
+        let mut tokens = self
+            .exchange_code_for_tokens(&callback_result.code, pkce, redirect_uri)
+            .await?;
+        self.introspect_if_opaque(&mut tokens).await;
+
+        tokens.save_to_disk(&self.cache_key)?;
+        Ok(tokens)
+    }
+
     /// Refresh access token using refresh token
     async fn refresh_access_token(&self) -> Result<String> {
         let refresh_token = {
@@ -150,7 +415,6 @@ impl OidcClient {
 
         tracing::debug!("Refreshing access token");
 
-        let client = reqwest::Client::new();
         let mut params = vec![
             ("grant_type", "refresh_token"),
             ("refresh_token", &refresh_token),
@@ -164,10 +428,8 @@ impl OidcClient {
             params.push(("client_secret", &client_secret_ref));
         }
 
-        let response = client
-            .post(&self.oidc_config.token_endpoint)
-            .form(&params)
-            .send()
+        let response = self
+            .post_to_provider(&self.oidc_config.token_endpoint, &params)
             .await?;
 
         if !response.status().is_success() {
@@ -178,10 +440,11 @@ impl OidcClient {
         }
 
         let token_response: TokenResponse = response.json().await?;
-        let tokens = TokenInfo::from(token_response);
+        let mut tokens = self.check_token_binding(TokenInfo::from(token_response));
+        self.introspect_if_opaque(&mut tokens).await;
 
         // Save and cache tokens
-        tokens.save_to_disk(&self.issuer_url)?;
+        tokens.save_to_disk(&self.cache_key)?;
         let access_token = tokens.access_token.clone();
 
         {
@@ -193,13 +456,66 @@ impl OidcClient {
         Ok(access_token)
     }
 
+    /// Exchange the login token for a token for the configured audience (RFC 8693)
+    async fn exchange_token(
+        &self,
+        exchange: &TokenExchange,
+        subject_token: &str,
+    ) -> Result<String> {
+        tracing::debug!("Exchanging access token for '{}'", exchange.target());
+
+        let scope = exchange.scopes.join(" ");
+        let mut params = exchange.params(subject_token, &scope);
+        params.push(("client_id", &self.client_id));
+
+        // Add client secret if present
+        if let Some(ref secret) = self.client_secret {
+            params.push(("client_secret", secret));
+        }
+
+        let response = self
+            .post_to_provider(&self.oidc_config.token_endpoint, &params)
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(ProxyError::Token(format!(
+                "Token exchange for '{}' failed with status {}: {}",
+                exchange.target(),
+                status,
+                body
+            )));
+        }
+
+        let token_response: TokenResponse = response.json().await?;
+        let mut tokens = self.check_token_binding(TokenInfo::from(token_response));
+        self.introspect_if_opaque(&mut tokens).await;
+
+        // Save and cache tokens
+        tokens.save_to_disk(&exchange.cache_key(&self.cache_key))?;
+        let access_token = tokens.access_token.clone();
+
+        {
+            let mut token_guard = self.exchanged_token_info.write().await;
+            *token_guard = Some(tokens);
+        }
+
+        tracing::debug!("Access token exchanged successfully");
+        Ok(access_token)
+    }
+
     /// Exchange authorization code for tokens
-    async fn exchange_code_for_tokens(&self, code: &str, pkce: &PkceParams) -> Result<TokenInfo> {
-        let client = reqwest::Client::new();
+    async fn exchange_code_for_tokens(
+        &self,
+        code: &str,
+        pkce: &PkceParams,
+        redirect_uri: &str,
+    ) -> Result<TokenInfo> {
         let mut params = vec![
             ("grant_type", "authorization_code"),
             ("code", code),
-            ("redirect_uri", &self.redirect_url),
+            ("redirect_uri", redirect_uri),
             ("client_id", &self.client_id),
             ("code_verifier", &pkce.code_verifier),
         ];
@@ -211,10 +527,8 @@ impl OidcClient {
             params.push(("client_secret", &client_secret_ref));
         }
 
-        let response = client
-            .post(&self.oidc_config.token_endpoint)
-            .form(&params)
-            .send()
+        let response = self
+            .post_to_provider(&self.oidc_config.token_endpoint, &params)
             .await?;
 
         if !response.status().is_success() {
@@ -227,24 +541,397 @@ impl OidcClient {
         }
 
         let token_response: TokenResponse = response.json().await?;
-        Ok(TokenInfo::from(token_response))
+        Ok(self.check_token_binding(TokenInfo::from(token_response)))
     }
 
     /// Build authorization URL with PKCE parameters
-    fn build_authorization_url(&self, state: &str, pkce: &PkceParams) -> Result<String> {
+    ///
+    /// With PAR (RFC 9126) the parameters are pushed to the provider first and
+    /// the URL only carries `client_id` and the returned `request_uri`.
+    async fn build_authorization_url(
+        &self,
+        state: &str,
+        pkce: &PkceParams,
+        redirect_uri: &str,
+        extra_params: &[(&str, &str)],
+    ) -> Result<String> {
+        let scope = self.scopes.read().await.join(" ");
+        let dpop_jkt = self.dpop.as_ref().map(|dpop| dpop.thumbprint());
+        let mut params = vec![
+            ("response_type", "code"),
+            ("client_id", self.client_id.as_str()),
+            ("redirect_uri", redirect_uri),
+            ("scope", scope.as_str()),
+            ("state", state),
+            ("code_challenge", pkce.code_challenge.as_str()),
+            ("code_challenge_method", "S256"),
+        ];
+
+        // Bind the authorization code to the DPoP key (RFC 9449, Section 10)
+        if let Some(ref dpop_jkt) = dpop_jkt {
+            params.push(("dpop_jkt", dpop_jkt));
+        }
+        // Configured parameters (prompt, login_hint etc.), unless set for this request
+        params.extend(
+            self.auth_params
+                .iter()
+                .filter(|(name, _)| !extra_params.iter().any(|(extra, _)| extra == name))
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        );
+        params.extend_from_slice(extra_params);
+
         let mut url = Url::parse(&self.oidc_config.authorization_endpoint)?;
 
-        url.query_pairs_mut()
-            .append_pair("response_type", "code")
-            .append_pair("client_id", &self.client_id)
-            .append_pair("redirect_uri", &self.redirect_url)
-            .append_pair("scope", &self.scopes.join(" "))
-            .append_pair("state", state)
-            .append_pair("code_challenge", &pkce.code_challenge)
-            .append_pair("code_challenge_method", "S256");
+        match self.par_endpoint()? {
+            Some(par_endpoint) => {
+                let request_uri = self
+                    .push_authorization_request(par_endpoint, &params)
+                    .await?;
+                url.query_pairs_mut()
+                    .append_pair("client_id", &self.client_id)
+                    .append_pair("request_uri", &request_uri);
+            }
+            None => {
+                url.query_pairs_mut().extend_pairs(params);
+            }
+        }
 
         Ok(url.to_string())
     }
+
+    /// Pushed authorization request endpoint to use, if any
+    fn par_endpoint(&self) -> Result<Option<&str>> {
+        let endpoint = self
+            .oidc_config
+            .pushed_authorization_request_endpoint
+            .as_deref();
+
+        match self.par {
+            ParMode::Never => {
+                if self.oidc_config.require_pushed_authorization_requests == Some(true) {
+                    tracing::warn!("Provider requires pushed authorization requests, but PAR is disabled");
+                }
+                Ok(None)
+            }
+            ParMode::Auto => Ok(endpoint),
+            ParMode::Always => endpoint.map(Some).ok_or_else(|| {
+                ProxyError::Config(
+                    "PAR is required, but the provider does not advertise a pushed_authorization_request_endpoint".to_string(),
+                )
+            }),
+        }
+    }
+
+    /// Push the authorization parameters to the provider and return the request URI
+    async fn push_authorization_request(
+        &self,
+        par_endpoint: &str,
+        params: &[(&str, &str)],
+    ) -> Result<String> {
+        #[derive(serde::Deserialize)]
+        struct ParResponse {
+            request_uri: String,
+            expires_in: Option<u64>,
+        }
+
+        let mut params = params.to_vec();
+
+        // Add client secret if present
+        if let Some(ref secret) = self.client_secret {
+            params.push(("client_secret", secret));
+        }
+
+        tracing::debug!("Pushing authorization request to {}", par_endpoint);
+
+        let response = self.post_to_provider(par_endpoint, &params).await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(ProxyError::Auth(format!(
+                "Pushed authorization request failed with status {}: {}",
+                status, body
+            )));
+        }
+
+        let par_response: ParResponse = response.json().await?;
+        tracing::debug!(
+            "Pushed authorization request accepted (expires in {}s)",
+            par_response
+                .expires_in
+                .map_or_else(|| "?".to_string(), |s| s.to_string())
+        );
+
+        Ok(par_response.request_uri)
+    }
+
+    /// POST a form to a provider endpoint, with a DPoP proof when enabled
+    ///
+    /// A `use_dpop_nonce` error (400 with a new `DPoP-Nonce`) is retried once
+    /// with the nonce.
+    async fn post_to_provider(
+        &self,
+        url: &str,
+        params: &[(&str, &str)],
+    ) -> Result<reqwest::Response> {
+        let mut nonce_retried = false;
+
+        loop {
+            let mut request = self.http.post(url).form(params);
+            if let Some(ref dpop) = self.dpop {
+                request = request.header(dpop::DPOP_HEADER, dpop.proof("POST", url, None)?);
+            }
+
+            let response = request.send().await?;
+
+            if let Some(ref dpop) = self.dpop {
+                let new_nonce = dpop.update_nonce(url, response.headers());
+                if new_nonce
+                    && !nonce_retried
+                    && response.status() == reqwest::StatusCode::BAD_REQUEST
+                {
+                    tracing::debug!("Provider requires a DPoP nonce, retrying");
+                    nonce_retried = true;
+                    continue;
+                }
+            }
+
+            return Ok(response);
+        }
+    }
+
+    /// Learn the expiry of an opaque token by introspection (RFC 7662), if possible
+    ///
+    /// Failures are only logged: the token is then used until the backend
+    /// rejects it.
+    async fn introspect_if_opaque(&self, tokens: &mut TokenInfo) {
+        let Some(ref endpoint) = self.oidc_config.introspection_endpoint else {
+            return;
+        };
+        if !tokens.needs_introspection() {
+            return;
+        }
+
+        match self.introspect(endpoint, &tokens.access_token).await {
+            Ok(response) => {
+                tracing::debug!(
+                    "Introspected opaque access token (active: {}, exp: {:?})",
+                    response.active,
+                    response.exp
+                );
+                tokens.apply_introspection(&response);
+            }
+            Err(e) => tracing::warn!("Token introspection failed, token expiry unknown: {}", e),
+        }
+    }
+
+    /// Introspect an access token at the provider's introspection endpoint
+    async fn introspect(&self, endpoint: &str, token: &str) -> Result<IntrospectionResponse> {
+        let mut params = vec![
+            ("token", token),
+            ("token_type_hint", "access_token"),
+            ("client_id", &self.client_id),
+        ];
+
+        // Add client secret if present
+        if let Some(ref secret) = self.client_secret {
+            params.push(("client_secret", secret));
+        }
+
+        let response = self.post_to_provider(endpoint, &params).await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(ProxyError::Token(format!(
+                "Token introspection failed with status {}: {}",
+                status, body
+            )));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Warn if DPoP is enabled but the provider issued a plain bearer token
+    fn check_token_binding(&self, tokens: TokenInfo) -> TokenInfo {
+        if self.dpop.is_some() && !tokens.is_dpop_bound() {
+            tracing::warn!(
+                "DPoP is enabled, but the provider issued a '{}' token that is not bound to the DPoP key",
+                tokens.token_type.as_deref().unwrap_or("Bearer")
+            );
+        }
+        tokens
+    }
+
+    /// Identity of the signed-in user, from userinfo or the cached ID token
+    ///
+    /// Never starts a login: returns `None` if no tokens are cached.
+    pub async fn identity(&self) -> Option<UserIdentity> {
+        let tokens = self.token_info.read().await.clone()?;
+
+        if let (true, Some(endpoint)) = (
+            tokens.is_valid(),
+            self.oidc_config.userinfo_endpoint.as_deref(),
+        ) {
+            let dpop = self.dpop.as_ref().filter(|_| tokens.is_dpop_bound());
+            let scheme = if dpop.is_some() { "DPoP" } else { "Bearer" };
+            let proof = match dpop {
+                Some(dpop) => dpop.proof("GET", endpoint, Some(&tokens.access_token)).ok(),
+                None => None,
+            };
+
+            let authorization = format!("{} {}", scheme, tokens.access_token);
+            match userinfo::fetch(&self.http, endpoint, &authorization, proof).await {
+                Ok(identity) => return Some(identity),
+                Err(e) => tracing::debug!("Userinfo unavailable, using ID token claims: {}", e),
+            }
+        }
+
+        tokens.identity()
+    }
+
+    /// Claims of the cached tokens (see `TokenInfo::claims`)
+    ///
+    /// With token exchange, the exchanged token's claims override those of
+    /// the login tokens. Never starts a login.
+    pub async fn claims(&self) -> Option<Map<String, Value>> {
+        let mut claims = self.token_info.read().await.as_ref()?.claims();
+        if self.exchange.is_some() {
+            if let Some(ref exchanged) = *self.exchanged_token_info.read().await {
+                claims.extend(jwt::decode_claims(&exchanged.access_token).unwrap_or_default());
+            }
+        }
+        Some(claims)
+    }
+
+    /// DPoP state, if sender-constrained tokens are enabled
+    pub fn dpop(&self) -> Option<&Arc<Dpop>> {
+        self.dpop.as_ref()
+    }
+
+    /// Whether the current backend token is DPoP-bound (uses the `DPoP` scheme)
+    pub async fn token_is_dpop_bound(&self) -> bool {
+        self.dpop.is_some()
+            && self
+                .backend_token_info()
+                .read()
+                .await
+                .as_ref()
+                .is_some_and(|tokens| tokens.is_dpop_bound())
+    }
+}
+
+/// Wait for the authorization response when no local browser is available
+///
+/// The callback listener keeps running (for `complete-login` or a browser on
+/// this host); when attached to a terminal, a pasted response is accepted too.
+async fn wait_for_manual_response(
+    listener: callback::CallbackListener,
+    pages: CallbackPages,
+    auth_url: &str,
+    state: &str,
+    issuer: &str,
+) -> Result<(
+    callback::CallbackResult,
+    Option<callback::CallbackResponder>,
+)> {
+    let interactive = std::io::stdin().is_terminal();
+
+    tracing::warn!("Open this URL in a browser on any machine: {}", auth_url);
+    tracing::warn!(
+        "After signing in, copy the full URL you are redirected to (the page may fail to load) {}run `authful-mcp-proxy-rs complete-login --url '<URL>'` on this machine",
+        if interactive { "and paste it here, or " } else { "and " }
+    );
+
+    if !interactive {
+        let (result, responder) = callback::run_callback_server(listener, pages).await?;
+        return Ok((result, Some(responder)));
+    }
+
+    tokio::select! {
+        result = callback::run_callback_server(listener, pages) => {
+            let (result, responder) = result?;
+            Ok((result, Some(responder)))
+        }
+        input = read_pasted_response() => Ok((pasted_response(&input, state, issuer)?, None)),
+    }
+}
+
+/// Authorization response from a redirected URL or code pasted into the terminal
+fn pasted_response(input: &str, state: &str, issuer: &str) -> Result<callback::CallbackResult> {
+    let mut params = callback::parse_authorization_response(input)?;
+    // A bare code pasted into our own terminal cannot be forged by a third
+    // party, and has neither state nor issuer to check
+    if params.state.is_none() && params.error.is_none() {
+        params.state = Some(state.to_string());
+        params.iss.get_or_insert_with(|| issuer.to_string());
+    }
+    params.into_result()
+}
+
+/// Read a non-empty line from the terminal; pends forever on end of input
+///
+/// Only waits for readiness while pending, so nothing is consumed from stdin
+/// once the browser callback has won and this future is dropped.
+#[cfg(unix)]
+async fn read_pasted_response() -> String {
+    use std::io::BufRead;
+    use tokio::io::unix::AsyncFd;
+
+    let Ok(stdin) = AsyncFd::new(std::io::stdin()) else {
+        return std::future::pending().await;
+    };
+
+    loop {
+        let Ok(mut guard) = stdin.readable().await else {
+            return std::future::pending().await;
+        };
+
+        // A terminal in canonical mode is readable once a full line was entered
+        let mut line = String::new();
+        let read = std::io::stdin().lock().read_line(&mut line);
+        match read {
+            Ok(0) | Err(_) => return std::future::pending().await,
+            Ok(_) if !line.trim().is_empty() => return line,
+            Ok(_) => guard.clear_ready(),
+        }
+    }
+}
+
+/// Read a non-empty line from the terminal; pends forever on end of input
+///
+/// Uses a detached thread because a blocking stdin read cannot be cancelled
+/// and would otherwise keep the runtime from shutting down.
+#[cfg(not(unix))]
+async fn read_pasted_response() -> String {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            match line {
+                Ok(line) if !line.trim().is_empty() => {
+                    let _ = tx.send(line);
+                    return;
+                }
+                Ok(_) => {}
+                Err(_) => return,
+            }
+        }
+    });
+
+    match rx.await {
+        Ok(line) => line,
+        Err(_) => std::future::pending().await,
+    }
+}
+
+/// Add scopes not yet present, keeping the order
+fn merge_scopes(scopes: &mut Vec<String>, additional: impl IntoIterator<Item = String>) {
+    for scope in additional {
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
 }
 
 /// Generate a random state parameter for CSRF protection
@@ -258,3 +945,436 @@ fn generate_state() -> String {
         .map(char::from)
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::token::TempStorage;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pushed_authorization_request() {
+        let _storage = TempStorage::new();
+        let mut server = mockito::Server::new_async().await;
+        let _discovery = server
+            .mock("GET", "/.well-known/openid-configuration")
+            .with_header("content-type", "application/json")
+            .with_body(
+                serde_json::json!({
+                    "issuer": server.url(),
+                    "authorization_endpoint": format!("{}/authorize", server.url()),
+                    "token_endpoint": format!("{}/token", server.url()),
+                    "pushed_authorization_request_endpoint": format!("{}/par", server.url()),
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let par = server
+            .mock("POST", "/par")
+            .match_body(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("state".into(), "state-1".into()),
+                mockito::Matcher::UrlEncoded("code_challenge_method".into(), "S256".into()),
+                mockito::Matcher::UrlEncoded("client_secret".into(), "secret".into()),
+            ]))
+            .with_status(201)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"request_uri": "urn:ietf:params:oauth:request_uri:abc", "expires_in": 60}"#,
+            )
+            .expect(1)
+            .create_async()
+            .await;
+
+        let mut client = OidcClient::new(
+            server.url(),
+            "client-1".to_string(),
+            Some("secret".to_string()),
+            vec!["openid".to_string()],
+            "http://localhost:8080/auth/callback".to_string(),
+        )
+        .await
+        .unwrap();
+
+        let pkce = PkceParams::generate();
+        let redirect_uri = "http://localhost:8080/auth/callback";
+        let url = client
+            .build_authorization_url("state-1", &pkce, redirect_uri, &[])
+            .await
+            .unwrap();
+        par.assert_async().await;
+
+        let url = Url::parse(&url).unwrap();
+        let pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("client_id".to_string(), "client-1".to_string()),
+                (
+                    "request_uri".to_string(),
+                    "urn:ietf:params:oauth:request_uri:abc".to_string()
+                ),
+            ]
+        );
+
+        // Without PAR everything goes into the front channel
+        client.par = ParMode::Never;
+        let url = client
+            .build_authorization_url("state-1", &pkce, redirect_uri, &[])
+            .await
+            .unwrap();
+        assert!(url.contains("code_challenge="));
+        assert!(!url.contains("request_uri="));
+    }
+
+    #[tokio::test]
+    async fn test_step_up_scopes_and_concurrent_reauthorization() {
+        let _storage = TempStorage::new();
+        let mut server = mockito::Server::new_async().await;
+        let _discovery = server
+            .mock("GET", "/.well-known/openid-configuration")
+            .with_header("content-type", "application/json")
+            .with_body(
+                serde_json::json!({
+                    "issuer": server.url(),
+                    "authorization_endpoint": format!("{}/authorize", server.url()),
+                    "token_endpoint": format!("{}/token", server.url()),
+                    "pushed_authorization_request_endpoint": format!("{}/par", server.url()),
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        // The pushed request shows what the step-up asks for; rejecting it
+        // ends the login before a browser is needed
+        let par = server
+            .mock("POST", "/par")
+            .match_body(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("scope".into(), "openid mcp:read mcp:write".into()),
+                mockito::Matcher::UrlEncoded("acr_values".into(), "urn:mfa".into()),
+                mockito::Matcher::UrlEncoded("max_age".into(), "0".into()),
+                mockito::Matcher::UrlEncoded("prompt".into(), "select_account".into()),
+            ]))
+            .with_status(400)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"error": "invalid_request"}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let mut client = OidcClient::new(
+            server.url(),
+            "client-1".to_string(),
+            None,
+            vec!["openid".to_string(), "mcp:read".to_string()],
+            "http://127.0.0.1:0/auth/callback".to_string(),
+        )
+        .await
+        .unwrap();
+        client.auth_params = vec![
+            ("prompt".to_string(), "select_account".to_string()),
+            ("acr_values".to_string(), "urn:password".to_string()),
+        ];
+
+        // Union of current and required scopes, without duplicates, kept for
+        // later logins
+        let challenge = AuthChallenge {
+            scope: Some("mcp:read mcp:write".to_string()),
+            acr_values: Some("urn:mfa".to_string()),
+            max_age: Some(0),
+            ..Default::default()
+        };
+        assert!(client.step_up(&challenge, "rejected").await.is_err());
+        par.assert_async().await;
+        assert_eq!(
+            *client.scopes.read().await,
+            vec!["openid", "mcp:read", "mcp:write"]
+        );
+
+        client.par = ParMode::Never;
+        let pkce = PkceParams::generate();
+        let url = client
+            .build_authorization_url(
+                "state-1",
+                &pkce,
+                "http://localhost:8080/auth/callback",
+                &[("acr_values", "urn:mfa"), ("max_age", "0")],
+            )
+            .await
+            .unwrap();
+        assert!(url.contains("acr_values=urn%3Amfa"));
+        assert!(url.contains("max_age=0"));
+        // Configured parameters stay, unless the step-up replaces them
+        assert!(url.contains("prompt=select_account"));
+        assert!(!url.contains("urn%3Apassword"));
+
+        // A token renewed by another request is reused instead of re-authorizing
+        *client.token_info.write().await = Some(TokenInfo::from(TokenResponse {
+            access_token: "renewed".to_string(),
+            refresh_token: None,
+            expires_in: Some(3600),
+            token_type: Some("Bearer".to_string()),
+            scope: None,
+            id_token: None,
+        }));
+        let challenge = AuthChallenge {
+            scope: Some("mcp:write".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            client.step_up(&challenge, "rejected").await.unwrap(),
+            "renewed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_renewals_refresh_once() {
+        let _storage = TempStorage::new();
+        let mut server = mockito::Server::new_async().await;
+        let _discovery = server
+            .mock("GET", "/.well-known/openid-configuration")
+            .with_header("content-type", "application/json")
+            .with_body(
+                serde_json::json!({
+                    "issuer": server.url(),
+                    "authorization_endpoint": format!("{}/authorize", server.url()),
+                    "token_endpoint": format!("{}/token", server.url()),
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let refresh_mock = server
+            .mock("POST", "/token")
+            .match_body(mockito::Matcher::UrlEncoded(
+                "grant_type".into(),
+                "refresh_token".into(),
+            ))
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"access_token": "renewed", "refresh_token": "refresh-2", "token_type": "Bearer", "expires_in": 3600}"#,
+            )
+            .expect(1)
+            .create_async()
+            .await;
+
+        let client = OidcClient::new(
+            server.url(),
+            "client-1".to_string(),
+            None,
+            vec!["openid".to_string()],
+            "http://localhost:8080/auth/callback".to_string(),
+        )
+        .await
+        .unwrap();
+        *client.token_info.write().await = Some(TokenInfo::from(TokenResponse {
+            access_token: "rejected".to_string(),
+            refresh_token: Some("refresh-1".to_string()),
+            expires_in: Some(3600),
+            token_type: Some("Bearer".to_string()),
+            scope: None,
+            id_token: None,
+        }));
+
+        // Requests rejected with the same token share one refresh
+        let (first, second) = tokio::join!(
+            client.renew_token("rejected"),
+            client.renew_token("rejected")
+        );
+        assert_eq!(first.unwrap(), "renewed");
+        assert_eq!(second.unwrap(), "renewed");
+        refresh_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_token_exchange_is_cached_separately() {
+        let _storage = TempStorage::new();
+        let mut server = mockito::Server::new_async().await;
+        let _discovery = server
+            .mock("GET", "/.well-known/openid-configuration")
+            .with_header("content-type", "application/json")
+            .with_body(
+                serde_json::json!({
+                    "issuer": server.url(),
+                    "authorization_endpoint": format!("{}/authorize", server.url()),
+                    "token_endpoint": format!("{}/token", server.url()),
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let exchange_mock = server
+            .mock("POST", "/token")
+            .match_body(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded(
+                    "grant_type".into(),
+                    super::super::exchange::GRANT_TYPE.into(),
+                ),
+                mockito::Matcher::UrlEncoded("subject_token".into(), "login-token".into()),
+                mockito::Matcher::UrlEncoded("audience".into(), "gateway".into()),
+            ]))
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"access_token": "gateway-token", "issued_token_type": "urn:ietf:params:oauth:token-type:access_token", "token_type": "Bearer", "expires_in": 300}"#,
+            )
+            .expect(1)
+            .create_async()
+            .await;
+
+        let mut client = OidcClient::new(
+            server.url(),
+            "client-1".to_string(),
+            None,
+            vec!["openid".to_string()],
+            "http://localhost:8080/auth/callback".to_string(),
+        )
+        .await
+        .unwrap();
+        let exchange = TokenExchange {
+            audience: Some("gateway".to_string()),
+            resource: None,
+            scopes: Vec::new(),
+        };
+        let exchanged_key = exchange.cache_key(&client.cache_key);
+        client.exchange = Some(exchange);
+        *client.token_info.write().await = Some(TokenInfo::from(TokenResponse {
+            access_token: "login-token".to_string(),
+            refresh_token: None,
+            expires_in: Some(3600),
+            token_type: Some("Bearer".to_string()),
+            scope: None,
+            id_token: None,
+        }));
+
+        assert_eq!(client.get_token().await.unwrap(), "gateway-token");
+        assert_eq!(client.get_token().await.unwrap(), "gateway-token");
+        exchange_mock.assert_async().await;
+
+        let cached = TokenInfo::load_from_disk(&exchanged_key).unwrap().unwrap();
+        assert_eq!(cached.access_token, "gateway-token");
+        assert!(TokenInfo::load_from_disk(&client.cache_key)
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pasted_code_with_issuer_identification() {
+        let _storage = TempStorage::new();
+        let mut server = mockito::Server::new_async().await;
+        let _discovery = server
+            .mock("GET", "/.well-known/openid-configuration")
+            .with_header("content-type", "application/json")
+            .with_body(
+                serde_json::json!({
+                    "issuer": server.url(),
+                    "authorization_endpoint": format!("{}/authorize", server.url()),
+                    "token_endpoint": format!("{}/token", server.url()),
+                    "authorization_response_iss_parameter_supported": true,
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let token = server
+            .mock("POST", "/token")
+            .match_body(mockito::Matcher::UrlEncoded(
+                "code".into(),
+                "pasted-code".into(),
+            ))
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"access_token": "token", "token_type": "Bearer", "expires_in": 300}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let client = OidcClient::new(
+            server.url(),
+            "client-1".to_string(),
+            None,
+            vec!["openid".to_string()],
+            "http://localhost:8080/auth/callback".to_string(),
+        )
+        .await
+        .unwrap();
+        let pkce = PkceParams::generate();
+        let redirect_uri = "http://localhost:8080/auth/callback";
+
+        // A pasted redirect URL must still carry the issuer
+        let pasted = pasted_response(
+            "http://localhost:8080/auth/callback?code=pasted-code&state=state-1",
+            "state-1",
+            &client.oidc_config.issuer,
+        )
+        .unwrap();
+        assert!(client
+            .complete_auth_flow(pasted, "state-1", &pkce, redirect_uri)
+            .await
+            .is_err());
+
+        // A bare code has no issuer to check
+        let pasted =
+            pasted_response(" pasted-code\n", "state-1", &client.oidc_config.issuer).unwrap();
+        let tokens = client
+            .complete_auth_flow(pasted, "state-1", &pkce, redirect_uri)
+            .await
+            .unwrap();
+        assert_eq!(tokens.access_token, "token");
+        token.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_opaque_tokens_are_introspected() {
+        let _storage = TempStorage::new();
+        let mut server = mockito::Server::new_async().await;
+        let _discovery = server
+            .mock("GET", "/.well-known/openid-configuration")
+            .with_header("content-type", "application/json")
+            .with_body(
+                serde_json::json!({
+                    "issuer": server.url(),
+                    "authorization_endpoint": format!("{}/authorize", server.url()),
+                    "token_endpoint": format!("{}/token", server.url()),
+                    "introspection_endpoint": format!("{}/introspect", server.url()),
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let introspection = server
+            .mock("POST", "/introspect")
+            .match_body(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("token".into(), "opaque-token".into()),
+                mockito::Matcher::UrlEncoded("client_secret".into(), "secret".into()),
+            ]))
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"active": true, "exp": 4102444800, "scope": "openid"}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let client = OidcClient::new(
+            server.url(),
+            "client-1".to_string(),
+            Some("secret".to_string()),
+            vec!["openid".to_string()],
+            "http://localhost:8080/auth/callback".to_string(),
+        )
+        .await
+        .unwrap();
+
+        let mut tokens = TokenInfo::from(TokenResponse {
+            access_token: "opaque-token".to_string(),
+            refresh_token: None,
+            expires_in: None,
+            token_type: Some("Bearer".to_string()),
+            scope: None,
+            id_token: None,
+        });
+        client.introspect_if_opaque(&mut tokens).await;
+        // Introspected once only
+        client.introspect_if_opaque(&mut tokens).await;
+
+        introspection.assert_async().await;
+        assert_eq!(tokens.expires_at(), Some(4102444800));
+        assert_eq!(tokens.scope.as_deref(), Some("openid"));
+    }
+}
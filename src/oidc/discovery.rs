@@ -14,18 +14,52 @@ pub struct OidcConfig {
     pub userinfo_endpoint: Option<String>,
     #[serde(default)]
     pub jwks_uri: Option<String>,
+    #[serde(default)]
+    pub revocation_endpoint: Option<String>,
+    #[serde(default)]
+    pub introspection_endpoint: Option<String>,
+    #[serde(default)]
+    pub end_session_endpoint: Option<String>,
+    #[serde(default)]
+    pub scopes_supported: Option<Vec<String>>,
+    #[serde(default)]
+    pub grant_types_supported: Option<Vec<String>>,
+    #[serde(default)]
+    pub code_challenge_methods_supported: Option<Vec<String>>,
+    #[serde(default)]
+    pub authorization_response_iss_parameter_supported: Option<bool>,
+    #[serde(default)]
+    pub pushed_authorization_request_endpoint: Option<String>,
+    #[serde(default)]
+    pub require_pushed_authorization_requests: Option<bool>,
+    #[serde(default)]
+    pub mtls_endpoint_aliases: Option<MtlsEndpointAliases>,
+}
+
+/// Endpoints to use with mutual TLS client authentication (RFC 8705, Section 5)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MtlsEndpointAliases {
+    #[serde(default)]
+    pub token_endpoint: Option<String>,
+    #[serde(default)]
+    pub pushed_authorization_request_endpoint: Option<String>,
+    #[serde(default)]
+    pub revocation_endpoint: Option<String>,
+    #[serde(default)]
+    pub introspection_endpoint: Option<String>,
+    #[serde(default)]
+    pub userinfo_endpoint: Option<String>,
 }
 
 impl OidcConfig {
     /// Discover OIDC configuration from issuer URL
-    pub async fn discover(issuer_url: &str) -> Result<Self> {
+    pub async fn discover(http: &reqwest::Client, issuer_url: &str) -> Result<Self> {
         let discovery_url = format!(
             "{}/.well-known/openid-configuration",
             issuer_url.trim_end_matches('/')
         );
 
-        let client = reqwest::Client::new();
-        let response = client
+        let response = http
             .get(&discovery_url)
             .timeout(std::time::Duration::from_secs(5))
             .send()
@@ -60,4 +94,116 @@ impl OidcConfig {
 
         Ok(config)
     }
+
+    /// Switch to the mutual TLS endpoint aliases, where the provider has any
+    ///
+    /// Used when a client certificate is configured, as providers may serve
+    /// certificate-bound tokens only on these endpoints.
+    pub fn use_mtls_endpoint_aliases(&mut self) {
+        let Some(aliases) = self.mtls_endpoint_aliases.clone() else {
+            return;
+        };
+
+        if let Some(endpoint) = aliases.token_endpoint {
+            self.token_endpoint = endpoint;
+        }
+        if let Some(endpoint) = aliases.pushed_authorization_request_endpoint {
+            self.pushed_authorization_request_endpoint = Some(endpoint);
+        }
+        if let Some(endpoint) = aliases.revocation_endpoint {
+            self.revocation_endpoint = Some(endpoint);
+        }
+        if let Some(endpoint) = aliases.introspection_endpoint {
+            self.introspection_endpoint = Some(endpoint);
+        }
+        if let Some(endpoint) = aliases.userinfo_endpoint {
+            self.userinfo_endpoint = Some(endpoint);
+        }
+    }
+
+    /// Validate the `iss` parameter of an authorization response (RFC 9207)
+    ///
+    /// Defends against mix-up attacks: a present `iss` must match the issuer
+    /// exactly, and providers advertising support must send it.
+    pub fn validate_authorization_response_issuer(&self, iss: Option<&str>) -> Result<()> {
+        match iss {
+            Some(iss) if iss != self.issuer => Err(ProxyError::Auth(format!(
+                "Authorization response issuer mismatch: expected '{}', got '{}' - possible mix-up attack",
+                self.issuer, iss
+            ))),
+            None if self.authorization_response_iss_parameter_supported == Some(true) => {
+                Err(ProxyError::Auth(
+                    "Authorization response is missing the 'iss' parameter the provider advertises"
+                        .to_string(),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(iss_supported: Option<bool>) -> OidcConfig {
+        serde_json::from_value(serde_json::json!({
+            "issuer": "https://auth.example.com",
+            "authorization_endpoint": "https://auth.example.com/authorize",
+            "token_endpoint": "https://auth.example.com/token",
+            "authorization_response_iss_parameter_supported": iss_supported,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_use_mtls_endpoint_aliases() {
+        let mut oidc_config: OidcConfig = serde_json::from_value(serde_json::json!({
+            "issuer": "https://auth.example.com",
+            "authorization_endpoint": "https://auth.example.com/authorize",
+            "token_endpoint": "https://auth.example.com/token",
+            "revocation_endpoint": "https://auth.example.com/revoke",
+            "mtls_endpoint_aliases": {
+                "token_endpoint": "https://mtls.auth.example.com/token"
+            }
+        }))
+        .unwrap();
+
+        oidc_config.use_mtls_endpoint_aliases();
+        assert_eq!(
+            oidc_config.token_endpoint,
+            "https://mtls.auth.example.com/token"
+        );
+        assert_eq!(
+            oidc_config.revocation_endpoint.as_deref(),
+            Some("https://auth.example.com/revoke")
+        );
+        assert_eq!(
+            oidc_config.authorization_endpoint,
+            "https://auth.example.com/authorize"
+        );
+    }
+
+    #[test]
+    fn test_validate_authorization_response_issuer() {
+        let supported = config(Some(true));
+        assert!(supported
+            .validate_authorization_response_issuer(Some("https://auth.example.com"))
+            .is_ok());
+        assert!(supported
+            .validate_authorization_response_issuer(Some("https://evil.example.com"))
+            .is_err());
+        assert!(supported
+            .validate_authorization_response_issuer(None)
+            .is_err());
+
+        // Without advertised support a missing iss is accepted, a wrong one is not
+        let unsupported = config(None);
+        assert!(unsupported
+            .validate_authorization_response_issuer(None)
+            .is_ok());
+        assert!(unsupported
+            .validate_authorization_response_issuer(Some("https://auth.example.com/"))
+            .is_err());
+    }
 }
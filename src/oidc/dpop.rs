@@ -0,0 +1,328 @@
+//! DPoP sender-constrained tokens (RFC 9449)
+//!
+//! Binds access and refresh tokens to a key pair held by the proxy, so a
+//! leaked token file is useless without the key. Every token request and every
+//! backend request carries a `DPoP` proof JWT signed with the key (ES256).
+//!
+//! One key pair is generated per profile and stored next to the token cache
+//! (`~/.mcp/authful_mcp_proxy/keys/`). Server-provided nonces (`DPoP-Nonce`)
+//! are remembered per origin and included in subsequent proofs.
+
+use super::token::TokenInfo;
+use crate::error::{ProxyError, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use reqwest::header::HeaderMap;
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// Header carrying DPoP proofs
+pub const DPOP_HEADER: &str = "DPoP";
+
+/// Header carrying server-provided nonces
+pub const DPOP_NONCE_HEADER: &str = "DPoP-Nonce";
+
+/// Key pair used to sign DPoP proofs
+pub struct DpopKey {
+    key_pair: EcdsaKeyPair,
+    rng: SystemRandom,
+}
+
+impl DpopKey {
+    /// Generate a new P-256 key pair, returning it with its PKCS#8 encoding
+    pub fn generate() -> Result<(Self, Vec<u8>)> {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+            .map_err(|_| ProxyError::Auth("Failed to generate DPoP key".to_string()))?;
+        let key = Self::from_pkcs8(pkcs8.as_ref())?;
+
+        Ok((key, pkcs8.as_ref().to_vec()))
+    }
+
+    /// Load a key pair from its PKCS#8 encoding
+    pub fn from_pkcs8(pkcs8: &[u8]) -> Result<Self> {
+        let rng = SystemRandom::new();
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8, &rng)
+            .map_err(|e| ProxyError::Auth(format!("Invalid DPoP key: {}", e)))?;
+
+        Ok(Self { key_pair, rng })
+    }
+
+    /// Load the key pair of a profile, generating and storing it on first use
+    pub fn load_or_generate(profile: Option<&str>) -> Result<Self> {
+        let path = Self::key_file_path(profile)?;
+
+        if path.exists() {
+            let contents = std::fs::read_to_string(&path)?;
+            let pkcs8 = URL_SAFE_NO_PAD.decode(contents.trim()).map_err(|e| {
+                ProxyError::Auth(format!("Invalid DPoP key file {}: {}", path.display(), e))
+            })?;
+            return Self::from_pkcs8(&pkcs8);
+        }
+
+        let (key, pkcs8) = Self::generate()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, URL_SAFE_NO_PAD.encode(pkcs8))?;
+
+        // The key makes the tokens usable: keep it private to the current user
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        }
+
+        tracing::info!("Generated DPoP key {}", path.display());
+        Ok(key)
+    }
+
+    /// Key file of a profile
+    fn key_file_path(profile: Option<&str>) -> Result<PathBuf> {
+        let tokens_dir = TokenInfo::get_storage_dir()?;
+        let file_name = format!("dpop_{}.key", profile.unwrap_or("default"));
+
+        Ok(tokens_dir.with_file_name("keys").join(file_name))
+    }
+
+    /// Public key as JWK
+    pub fn jwk(&self) -> Value {
+        // Uncompressed point: 0x04 || x || y
+        let point = self.key_pair.public_key().as_ref();
+        json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..65]),
+        })
+    }
+
+    /// JWK SHA-256 thumbprint (RFC 7638), used as `dpop_jkt`
+    pub fn thumbprint(&self) -> String {
+        let jwk = self.jwk();
+        // Required members in lexicographic order, no whitespace
+        let canonical = format!(
+            r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+            jwk["x"].as_str().unwrap_or_default(),
+            jwk["y"].as_str().unwrap_or_default()
+        );
+        URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+    }
+
+    /// Create a DPoP proof JWT for a request
+    ///
+    /// `access_token` adds the `ath` claim (for resource requests), `nonce`
+    /// the server-provided nonce.
+    pub fn proof(
+        &self,
+        method: &str,
+        url: &str,
+        access_token: Option<&str>,
+        nonce: Option<&str>,
+    ) -> Result<String> {
+        let header = json!({
+            "typ": "dpop+jwt",
+            "alg": "ES256",
+            "jwk": self.jwk(),
+        });
+
+        let iat = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut claims = json!({
+            "jti": jti(),
+            "htm": method,
+            "htu": htu(url)?,
+            "iat": iat,
+        });
+        if let Some(token) = access_token {
+            claims["ath"] = json!(URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes())));
+        }
+        if let Some(nonce) = nonce {
+            claims["nonce"] = json!(nonce);
+        }
+
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let signature = self
+            .key_pair
+            .sign(&self.rng, signing_input.as_bytes())
+            .map_err(|_| ProxyError::Auth("Failed to sign DPoP proof".to_string()))?;
+
+        Ok(format!(
+            "{}.{}",
+            signing_input,
+            URL_SAFE_NO_PAD.encode(signature.as_ref())
+        ))
+    }
+}
+
+/// DPoP key together with the nonces received from servers
+pub struct Dpop {
+    key: DpopKey,
+    nonces: Mutex<HashMap<String, String>>,
+}
+
+impl Dpop {
+    pub fn new(key: DpopKey) -> Self {
+        Self {
+            key,
+            nonces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// JWK thumbprint of the key
+    pub fn thumbprint(&self) -> String {
+        self.key.thumbprint()
+    }
+
+    /// Create a proof for a request, including the latest nonce of its origin
+    pub fn proof(&self, method: &str, url: &str, access_token: Option<&str>) -> Result<String> {
+        let nonce = self.nonces.lock().unwrap().get(&origin(url)).cloned();
+        self.key.proof(method, url, access_token, nonce.as_deref())
+    }
+
+    /// Remember the nonce sent by a server
+    ///
+    /// Returns `true` if the nonce is new, i.e. a request rejected for lack of
+    /// a (fresh) nonce is worth retrying.
+    pub fn update_nonce(&self, url: &str, headers: &HeaderMap) -> bool {
+        let Some(nonce) = headers
+            .get(DPOP_NONCE_HEADER)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return false;
+        };
+
+        let previous = self
+            .nonces
+            .lock()
+            .unwrap()
+            .insert(origin(url), nonce.to_string());
+        previous.as_deref() != Some(nonce)
+    }
+}
+
+/// Check whether a resource server response demands a DPoP nonce
+pub fn is_nonce_challenge(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(reqwest::header::WWW_AUTHENTICATE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.contains("use_dpop_nonce"))
+}
+
+/// `htu` claim: the request URL without query and fragment
+fn htu(url: &str) -> Result<String> {
+    let mut url = Url::parse(url)?;
+    url.set_query(None);
+    url.set_fragment(None);
+    Ok(url.to_string())
+}
+
+fn origin(url: &str) -> String {
+    Url::parse(url)
+        .map(|url| url.origin().ascii_serialization())
+        .unwrap_or_else(|_| url.to_string())
+}
+
+fn jti() -> String {
+    use rand::distributions::Alphanumeric;
+    use rand::Rng;
+
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_FIXED};
+
+    fn decode(segment: &str) -> Value {
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(segment).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_proof_is_signed_and_bound() {
+        let (key, pkcs8) = DpopKey::generate().unwrap();
+        let proof = key
+            .proof(
+                "POST",
+                "https://mcp.example.com/mcp?session=1#frag",
+                Some("access-token"),
+                Some("nonce-1"),
+            )
+            .unwrap();
+
+        let parts: Vec<&str> = proof.split('.').collect();
+        let header = decode(parts[0]);
+        let claims = decode(parts[1]);
+
+        assert_eq!(header["typ"], "dpop+jwt");
+        assert_eq!(header["alg"], "ES256");
+        assert_eq!(header["jwk"], key.jwk());
+        assert_eq!(claims["htm"], "POST");
+        assert_eq!(claims["htu"], "https://mcp.example.com/mcp");
+        assert_eq!(claims["nonce"], "nonce-1");
+        assert_eq!(
+            claims["ath"],
+            URL_SAFE_NO_PAD.encode(Sha256::digest(b"access-token"))
+        );
+
+        // Signature verifies with the public key; reloading yields the same key
+        let public_key = UnparsedPublicKey::new(
+            &ECDSA_P256_SHA256_FIXED,
+            key.key_pair.public_key().as_ref().to_vec(),
+        );
+        let signing_input = format!("{}.{}", parts[0], parts[1]);
+        let signature = URL_SAFE_NO_PAD.decode(parts[2]).unwrap();
+        assert!(public_key
+            .verify(signing_input.as_bytes(), &signature)
+            .is_ok());
+        assert_eq!(
+            DpopKey::from_pkcs8(&pkcs8).unwrap().thumbprint(),
+            key.thumbprint()
+        );
+    }
+
+    #[test]
+    fn test_nonces_are_tracked_per_origin() {
+        let (key, _) = DpopKey::generate().unwrap();
+        let dpop = Dpop::new(key);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(DPOP_NONCE_HEADER, "n1".parse().unwrap());
+        assert!(dpop.update_nonce("https://auth.example.com/token", &headers));
+        assert!(!dpop.update_nonce("https://auth.example.com/par", &headers));
+
+        let nonce_of = |url: &str| {
+            let proof = dpop.proof("POST", url, None).unwrap();
+            decode(proof.split('.').nth(1).unwrap())["nonce"].clone()
+        };
+        assert_eq!(nonce_of("https://auth.example.com/token"), "n1");
+        assert_eq!(nonce_of("https://mcp.example.com/mcp"), Value::Null);
+
+        let mut challenge = HeaderMap::new();
+        challenge.insert(
+            reqwest::header::WWW_AUTHENTICATE,
+            r#"DPoP error="use_dpop_nonce", error_description="Resource server requires nonce""#
+                .parse()
+                .unwrap(),
+        );
+        assert!(is_nonce_challenge(&challenge));
+    }
+}
@@ -0,0 +1,141 @@
+//! OAuth 2.0 Token Exchange (RFC 8693)
+//!
+//! Gateways in front of the MCP backend may only accept tokens issued for
+//! their own audience. With token exchange configured, the token from the
+//! interactive login is the subject token and the backend receives a token
+//! exchanged for the configured audience / resource / scopes instead.
+//!
+//! Exchanged tokens are cached separately from the login tokens and exchanged
+//! again when they expire while the login token is still valid.
+
+use super::TokenCacheKey;
+use crate::config::Config;
+
+/// Grant type of token exchange requests
+pub const GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+
+/// Token type identifier of access tokens
+pub const ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+
+/// Target of the token exchange
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenExchange {
+    pub audience: Option<String>,
+    pub resource: Option<String>,
+    pub scopes: Vec<String>,
+}
+
+impl TokenExchange {
+    /// Token exchange of the configuration, if an audience or resource is set
+    pub fn from_config(config: &Config) -> Option<Self> {
+        if config.token_exchange_audience.is_none() && config.token_exchange_resource.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            audience: config.token_exchange_audience.clone(),
+            resource: config.token_exchange_resource.clone(),
+            scopes: config
+                .token_exchange_scopes
+                .as_deref()
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(String::from)
+                .collect(),
+        })
+    }
+
+    /// Audience and resource for display and cache keys
+    ///
+    /// Example: `gateway`, `https://gateway.example.com` or
+    ///          `gateway (https://gateway.example.com)`
+    pub fn target(&self) -> String {
+        match (self.audience.as_deref(), self.resource.as_deref()) {
+            (Some(audience), Some(resource)) => format!("{} ({})", audience, resource),
+            (Some(target), None) | (None, Some(target)) => target.to_string(),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// Cache key of the exchanged token, derived from the login token's key
+    pub fn cache_key(&self, subject_key: &TokenCacheKey) -> TokenCacheKey {
+        subject_key
+            .clone()
+            .exchanged_for(&self.target(), &self.scopes)
+    }
+
+    /// Form parameters of the exchange request (without client credentials)
+    pub fn params<'a>(&'a self, subject_token: &'a str, scope: &'a str) -> Vec<(&'a str, &'a str)> {
+        let mut params = vec![
+            ("grant_type", GRANT_TYPE),
+            ("subject_token", subject_token),
+            ("subject_token_type", ACCESS_TOKEN_TYPE),
+            ("requested_token_type", ACCESS_TOKEN_TYPE),
+        ];
+        if let Some(ref audience) = self.audience {
+            params.push(("audience", audience));
+        }
+        if let Some(ref resource) = self.resource {
+            params.push(("resource", resource));
+        }
+        if !scope.is_empty() {
+            params.push(("scope", scope));
+        }
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::token::TempStorage;
+    use super::super::TokenInfo;
+    use super::*;
+
+    #[test]
+    fn test_exchange_from_config_and_cache_key() {
+        let _storage = TempStorage::new();
+        assert!(TokenExchange::from_config(&Config::default()).is_none());
+
+        let config = Config {
+            oidc_issuer_url: "https://auth.example.com".to_string(),
+            oidc_client_id: "client-1".to_string(),
+            backend_url: "https://gateway.example.com/mcp".to_string(),
+            token_exchange_resource: Some("https://gateway.example.com".to_string()),
+            token_exchange_scopes: Some("mcp:read mcp:write".to_string()),
+            ..Default::default()
+        };
+        let exchange = TokenExchange::from_config(&config).unwrap();
+        assert_eq!(exchange.target(), "https://gateway.example.com");
+
+        let subject_key = TokenCacheKey::from_config(&config);
+        let key = exchange.cache_key(&subject_key);
+        assert_ne!(key, subject_key);
+        assert_eq!(
+            key.exchanged_for.as_deref(),
+            Some("https://gateway.example.com")
+        );
+        assert_eq!(key.scopes, vec!["mcp:read", "mcp:write"]);
+
+        let params = exchange.params("subject", "mcp:read mcp:write");
+        assert!(params.contains(&("grant_type", GRANT_TYPE)));
+        assert!(params.contains(&("resource", "https://gateway.example.com")));
+        assert!(!params.iter().any(|(name, _)| *name == "audience"));
+
+        // Same audience, different resources: separate cache entries
+        let for_resource = |resource: &str| TokenExchange {
+            audience: Some("gateway".to_string()),
+            resource: Some(resource.to_string()),
+            scopes: Vec::new(),
+        };
+        let a = for_resource("https://a.example.com").cache_key(&subject_key);
+        let b = for_resource("https://b.example.com").cache_key(&subject_key);
+        assert_ne!(
+            TokenInfo::get_token_file_path(&a).unwrap(),
+            TokenInfo::get_token_file_path(&b).unwrap()
+        );
+        assert_eq!(
+            a.exchanged_for.as_deref(),
+            Some("gateway (https://a.example.com)")
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Token introspection (RFC 7662)
+//!
+//! Opaque access tokens issued without `expires_in` carry no expiry the proxy
+//! could see, so they would be used until the backend rejects them. If the
+//! provider advertises an `introspection_endpoint`, such tokens are
+//! introspected once after they are obtained; the returned `exp`, `active`
+//! and `scope` are stored with the cached tokens so they are refreshed ahead
+//! of expiry like any other token.
+
+use serde::Deserialize;
+
+/// Introspection response (only the members the proxy uses)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    /// Expiry as Unix timestamp
+    #[serde(default)]
+    pub exp: Option<u64>,
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oidc::{TokenInfo, TokenResponse};
+
+    fn opaque_token() -> TokenInfo {
+        TokenInfo::from(TokenResponse {
+            access_token: "2YotnFZFEjr1zCsicMWpAA".to_string(),
+            refresh_token: Some("refresh".to_string()),
+            expires_in: None,
+            token_type: Some("Bearer".to_string()),
+            scope: None,
+            id_token: None,
+        })
+    }
+
+    #[test]
+    fn test_apply_introspection() {
+        let mut token = opaque_token();
+        assert!(token.needs_introspection());
+
+        let response: IntrospectionResponse = serde_json::from_str(
+            r#"{"active": true, "exp": 4102444800, "scope": "openid mcp:read", "client_id": "c"}"#,
+        )
+        .unwrap();
+        token.apply_introspection(&response);
+
+        assert!(!token.needs_introspection());
+        assert_eq!(token.expires_at(), Some(4102444800));
+        assert_eq!(token.scope.as_deref(), Some("openid mcp:read"));
+        assert!(token.is_valid());
+
+        // Inactive tokens are treated as expired, so they get refreshed
+        let mut token = opaque_token();
+        token.apply_introspection(&IntrospectionResponse::default());
+        assert!(!token.is_valid());
+        assert!(!token.needs_introspection());
+    }
+}
@@ -0,0 +1,60 @@
+//! JWT inspection helpers
+//!
+//! Decodes JWT payloads for display and diagnostics. Signatures are NOT
+//! verified: the proxy only forwards tokens, it never trusts their claims.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde_json::{Map, Value};
+
+/// Check whether a token looks like a JWT (three base64url segments)
+pub fn is_jwt(token: &str) -> bool {
+    decode_claims(token).is_some()
+}
+
+/// Decode the claims of a JWT without verifying its signature
+///
+/// Returns `None` for opaque tokens or malformed JWTs.
+pub fn decode_claims(token: &str) -> Option<Map<String, Value>> {
+    let mut parts = token.split('.');
+    let (_header, payload, _signature) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+
+    // Some issuers pad their segments even though RFC 7515 forbids it
+    let bytes = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    match serde_json::from_slice(&bytes).ok()? {
+        Value::Object(claims) => Some(claims),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_jwt(claims: &Value) -> String {
+        format!(
+            "{}.{}.signature",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256"}"#),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        )
+    }
+
+    #[test]
+    fn test_decode_claims() {
+        let token = make_jwt(&serde_json::json!({"sub": "user-1", "exp": 1700000000}));
+        let claims = decode_claims(&token).unwrap();
+
+        assert_eq!(claims["sub"], "user-1");
+        assert_eq!(claims["exp"], 1700000000);
+        assert!(is_jwt(&token));
+    }
+
+    #[test]
+    fn test_opaque_tokens_are_not_jwts() {
+        assert!(!is_jwt("2YotnFZFEjr1zCsicMWpAA"));
+        assert!(!is_jwt("a.b.c"));
+        assert!(!is_jwt("a.b.c.d"));
+    }
+}
@@ -0,0 +1,245 @@
+//! Sign-out support
+//!
+//! Revokes tokens at the provider's revocation endpoint (RFC 7009), optionally
+//! opens the RP-initiated logout page (`end_session_endpoint`) and removes the
+//! cached tokens from disk.
+
+use super::{OidcConfig, TokenCacheKey, TokenInfo};
+use crate::error::{ProxyError, Result};
+use crate::http::{HttpClientFactory, Target};
+use url::Url;
+
+/// Revoke a single token at the provider's revocation endpoint (RFC 7009)
+pub async fn revoke_token(
+    http: &reqwest::Client,
+    oidc_config: &OidcConfig,
+    client_id: &str,
+    client_secret: Option<&str>,
+    token: &str,
+    token_type_hint: &str,
+) -> Result<()> {
+    let revocation_endpoint = oidc_config.revocation_endpoint.as_deref().ok_or_else(|| {
+        ProxyError::Token("Provider does not advertise a revocation_endpoint".to_string())
+    })?;
+
+    let mut params = vec![
+        ("token", token),
+        ("token_type_hint", token_type_hint),
+        ("client_id", client_id),
+    ];
+
+    // Add client secret if present
+    if let Some(secret) = client_secret {
+        params.push(("client_secret", secret));
+    }
+
+    let response = http.post(revocation_endpoint).form(&params).send().await?;
+
+    // Per RFC 7009 the server responds 200 for both revoked and unknown tokens
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(ProxyError::Token(format!(
+            "Token revocation failed with status {}: {}",
+            status, body
+        )));
+    }
+
+    Ok(())
+}
+
+/// Build the RP-initiated logout URL, if the provider supports it
+pub fn end_session_url(
+    oidc_config: &OidcConfig,
+    client_id: &str,
+    id_token_hint: Option<&str>,
+) -> Result<Option<String>> {
+    let Some(ref endpoint) = oidc_config.end_session_endpoint else {
+        return Ok(None);
+    };
+
+    let mut url = Url::parse(endpoint)?;
+    url.query_pairs_mut().append_pair("client_id", client_id);
+    if let Some(id_token) = id_token_hint {
+        url.query_pairs_mut().append_pair("id_token_hint", id_token);
+    }
+
+    Ok(Some(url.to_string()))
+}
+
+/// Sign out of a single token cache entry
+///
+/// Revocation and end-session failures are logged but never prevent the local
+/// cache entry from being removed.
+pub async fn logout(
+    http: &HttpClientFactory,
+    key: &TokenCacheKey,
+    client_secret: Option<&str>,
+    end_session: bool,
+) -> Result<()> {
+    let Some(tokens) = TokenInfo::load_from_disk(key)? else {
+        TokenInfo::remove_from_disk(key)?;
+        tracing::info!("No cached tokens for {} ({})", key.issuer, key.client_id);
+        return Ok(());
+    };
+
+    let http_client = http.client(Target::Provider)?;
+    match OidcConfig::discover(&http_client, &key.issuer).await {
+        Ok(mut oidc_config) => {
+            if http.has_client_certificate() {
+                oidc_config.use_mtls_endpoint_aliases();
+            }
+            revoke_all(&http_client, &oidc_config, key, client_secret, &tokens).await;
+
+            if end_session {
+                open_end_session(&oidc_config, key, tokens.id_token.as_deref());
+            }
+        }
+        Err(e) => {
+            tracing::warn!("Skipping token revocation for {}: {}", key.issuer, e);
+        }
+    }
+
+    TokenInfo::remove_from_disk(key)?;
+    tracing::info!("Signed out of {} ({})", key.issuer, key.client_id);
+    Ok(())
+}
+
+/// Sign out of every cached token entry (all issuers and profiles)
+///
+/// `client_secret` is only used for entries belonging to `client_id`. A
+/// failing entry does not stop the others from being signed out; the
+/// failures are reported together at the end.
+pub async fn logout_all(
+    http: &HttpClientFactory,
+    client_id: &str,
+    client_secret: Option<&str>,
+    end_session: bool,
+) -> Result<()> {
+    let entries = TokenInfo::list_cached()?;
+    if entries.is_empty() {
+        tracing::info!("No cached tokens found");
+        return Ok(());
+    }
+
+    let total = entries.len();
+    let mut failed = 0;
+    for (path, tokens) in entries {
+        let result = match tokens.cache_key {
+            Some(ref key) => {
+                let secret = client_secret.filter(|_| key.client_id == client_id);
+                logout(http, key, secret, end_session).await
+            }
+            None => {
+                // Legacy entry: we don't know which client it belongs to
+                std::fs::remove_file(&path)
+                    .map(|()| tracing::info!("Removed legacy token file {:?}", path))
+                    .map_err(ProxyError::from)
+            }
+        };
+
+        if let Err(e) = result {
+            tracing::error!("Failed to sign out of {:?}: {}", path, e);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(ProxyError::Token(format!(
+            "Failed to sign out of {} of {} cached token entries",
+            failed, total
+        )));
+    }
+
+    Ok(())
+}
+
+async fn revoke_all(
+    http: &reqwest::Client,
+    oidc_config: &OidcConfig,
+    key: &TokenCacheKey,
+    client_secret: Option<&str>,
+    tokens: &TokenInfo,
+) {
+    if oidc_config.revocation_endpoint.is_none() {
+        tracing::warn!(
+            "{} does not support token revocation; tokens stay valid until they expire",
+            key.issuer
+        );
+        return;
+    }
+
+    // Revoke the refresh token first: most providers revoke the access tokens
+    // issued from it as well
+    let mut to_revoke = Vec::new();
+    if let Some(ref refresh_token) = tokens.refresh_token {
+        to_revoke.push((refresh_token.as_str(), "refresh_token"));
+    }
+    to_revoke.push((tokens.access_token.as_str(), "access_token"));
+
+    for (token, hint) in to_revoke {
+        match revoke_token(
+            http,
+            oidc_config,
+            &key.client_id,
+            client_secret,
+            token,
+            hint,
+        )
+        .await
+        {
+            Ok(()) => tracing::debug!("Revoked {}", hint),
+            Err(e) => tracing::warn!("Failed to revoke {}: {}", hint, e),
+        }
+    }
+}
+
+fn open_end_session(oidc_config: &OidcConfig, key: &TokenCacheKey, id_token: Option<&str>) {
+    match end_session_url(oidc_config, &key.client_id, id_token) {
+        Ok(Some(url)) => {
+            tracing::info!("Opening browser to end the provider session: {}", url);
+            if let Err(e) = webbrowser::open(&url) {
+                tracing::warn!("Failed to open browser: {}", e);
+                tracing::warn!("Please open this URL in your browser: {}", url);
+            }
+        }
+        Ok(None) => {
+            tracing::warn!("{} does not advertise an end_session_endpoint", key.issuer);
+        }
+        Err(e) => tracing::warn!("Invalid end_session_endpoint: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn oidc_config(end_session_endpoint: Option<&str>) -> OidcConfig {
+        serde_json::from_value(serde_json::json!({
+            "issuer": "https://auth.example.com",
+            "authorization_endpoint": "https://auth.example.com/auth",
+            "token_endpoint": "https://auth.example.com/token",
+            "end_session_endpoint": end_session_endpoint,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_end_session_url() {
+        let config = oidc_config(Some("https://auth.example.com/logout"));
+        let url = end_session_url(&config, "client", Some("id.token.value"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            url,
+            "https://auth.example.com/logout?client_id=client&id_token_hint=id.token.value"
+        );
+    }
+
+    #[test]
+    fn test_end_session_url_unsupported() {
+        let config = oidc_config(None);
+        assert!(end_session_url(&config, "client", None).unwrap().is_none());
+    }
+}
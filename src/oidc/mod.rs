@@ -1,12 +1,20 @@
 //! OIDC authentication module
 
 pub mod callback;
+pub mod challenge;
 pub mod client;
 pub mod discovery;
+pub mod dpop;
+pub mod exchange;
+pub mod introspection;
+pub mod jwt;
+pub mod logout;
+pub mod pages;
 pub mod pkce;
 pub mod token;
+pub mod userinfo;
 
 pub use client::OidcClient;
 pub use discovery::OidcConfig;
 pub use pkce::PkceParams;
-pub use token::{TokenInfo, TokenResponse};
+pub use token::{TokenCacheKey, TokenInfo, TokenResponse};
@@ -0,0 +1,271 @@
+//! Callback pages
+//!
+//! Renders the page the browser shows after the OAuth redirect. Built-in pages
+//! are used unless template files are configured. Templates use `{{name}}`
+//! placeholders:
+//!
+//! - `{{issuer}}`: issuer URL of the provider
+//! - `{{user}}`: signed-in user (success page; "your account" if unknown)
+//! - `{{error}}`: error message (error page)
+//! - `{{script}}`: auto-close / redirect snippet (inserted before `</body>` if absent)
+//!
+//! All values are HTML-escaped, as most of them originate from the provider.
+
+use crate::config::Config;
+use crate::error::{ProxyError, Result};
+
+const DEFAULT_SUCCESS_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Signed in</title>
+<style>
+  body { margin: 0; min-height: 100vh; display: flex; align-items: center; justify-content: center;
+         font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, sans-serif;
+         background: #f4f6f8; color: #1f2933; }
+  main { background: #fff; padding: 2.5rem 3rem; border-radius: 12px; max-width: 28rem;
+         box-shadow: 0 4px 24px rgba(0, 0, 0, 0.08); text-align: center; }
+  .icon { font-size: 2.5rem; color: #2f9e44; }
+  h1 { font-size: 1.4rem; margin: 0.5rem 0 1rem; }
+  p { line-height: 1.5; margin: 0.5rem 0; }
+  .muted { color: #7b8794; font-size: 0.9rem; word-break: break-all; }
+</style>
+</head>
+<body>
+<main>
+  <div class="icon">&#10003;</div>
+  <h1>Signed in</h1>
+  <p>You are signed in as <strong>{{user}}</strong>.</p>
+  <p>You can close this window and return to your application.</p>
+  <p class="muted">{{issuer}}</p>
+</main>
+{{script}}
+</body>
+</html>
+"#;
+
+const DEFAULT_ERROR_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Sign-in failed</title>
+<style>
+  body { margin: 0; min-height: 100vh; display: flex; align-items: center; justify-content: center;
+         font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, sans-serif;
+         background: #f4f6f8; color: #1f2933; }
+  main { background: #fff; padding: 2.5rem 3rem; border-radius: 12px; max-width: 28rem;
+         box-shadow: 0 4px 24px rgba(0, 0, 0, 0.08); text-align: center; }
+  .icon { font-size: 2.5rem; color: #c92a2a; }
+  h1 { font-size: 1.4rem; margin: 0.5rem 0 1rem; }
+  p { line-height: 1.5; margin: 0.5rem 0; }
+  .error { background: #fff5f5; color: #c92a2a; padding: 0.75rem; border-radius: 6px;
+           word-break: break-word; }
+  .muted { color: #7b8794; font-size: 0.9rem; word-break: break-all; }
+</style>
+</head>
+<body>
+<main>
+  <div class="icon">&#10007;</div>
+  <h1>Sign-in failed</h1>
+  <p class="error">{{error}}</p>
+  <p>You can close this window. Check the application logs for details.</p>
+  <p class="muted">{{issuer}}</p>
+</main>
+{{script}}
+</body>
+</html>
+"#;
+
+/// Delay before closing or leaving the success page
+const SUCCESS_DELAY_SECS: u32 = 2;
+
+/// Pages shown in the browser after the OAuth redirect
+#[derive(Debug, Clone, Default)]
+pub struct CallbackPages {
+    issuer: String,
+    success_template: Option<String>,
+    error_template: Option<String>,
+    success_redirect_url: Option<String>,
+    auto_close: bool,
+}
+
+impl CallbackPages {
+    /// Built-in pages for an issuer
+    pub fn new(issuer: &str) -> Self {
+        Self {
+            issuer: issuer.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Pages for an issuer as configured (template files are read here)
+    pub fn from_config(config: &Config, issuer: &str) -> Result<Self> {
+        let read = |path: &Option<std::path::PathBuf>| {
+            path.as_ref()
+                .map(|path| {
+                    std::fs::read_to_string(path).map_err(|e| {
+                        ProxyError::Config(format!(
+                            "Failed to read callback page template {}: {}",
+                            path.display(),
+                            e
+                        ))
+                    })
+                })
+                .transpose()
+        };
+
+        Ok(Self {
+            issuer: issuer.to_string(),
+            success_template: read(&config.callback_success_template)?,
+            error_template: read(&config.callback_error_template)?,
+            success_redirect_url: config.callback_success_redirect.clone(),
+            auto_close: config.callback_auto_close,
+        })
+    }
+
+    /// Page shown after a successful sign-in
+    pub fn success(&self, user: Option<&str>) -> String {
+        let script = if let Some(ref url) = self.success_redirect_url {
+            format!(
+                r#"<meta http-equiv="refresh" content="{};url={}">"#,
+                SUCCESS_DELAY_SECS,
+                html_escape(url)
+            )
+        } else if self.auto_close {
+            // Browsers only let scripts close tabs they consider script-opened;
+            // elsewhere this is a no-op and the page stays visible
+            format!(
+                "<script>setTimeout(function () {{ window.close(); }}, {});</script>",
+                SUCCESS_DELAY_SECS * 1000
+            )
+        } else {
+            String::new()
+        };
+
+        render(
+            self.success_template
+                .as_deref()
+                .unwrap_or(DEFAULT_SUCCESS_PAGE),
+            &[
+                ("issuer", &self.issuer),
+                ("user", user.unwrap_or("your account")),
+            ],
+            &script,
+        )
+    }
+
+    /// Page shown when the sign-in failed
+    pub fn error(&self, error: &str) -> String {
+        render(
+            self.error_template.as_deref().unwrap_or(DEFAULT_ERROR_PAGE),
+            &[("issuer", &self.issuer), ("error", error)],
+            "",
+        )
+    }
+}
+
+/// Escape text for use in HTML content and attribute values
+pub fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Substitute placeholders in a single pass (values are never re-scanned)
+///
+/// Unknown placeholders are left untouched. `script` is trusted markup and is
+/// inserted as-is.
+fn render(template: &str, values: &[(&str, &str)], script: &str) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    let mut script_placed = false;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+
+        let Some(end) = after.find("}}") else {
+            output.push_str(&rest[start..]);
+            rest = "";
+            break;
+        };
+
+        let name = after[..end].trim();
+        if name == "script" {
+            output.push_str(script);
+            script_placed = true;
+        } else if let Some((_, value)) = values.iter().find(|(key, _)| *key == name) {
+            output.push_str(&html_escape(value));
+        } else {
+            output.push_str(&rest[start..start + 2 + end + 2]);
+        }
+        rest = &after[end + 2..];
+    }
+    output.push_str(rest);
+
+    if !script_placed && !script.is_empty() {
+        match output.rfind("</body>") {
+            Some(pos) => output.insert_str(pos, script),
+            None => output.push_str(script),
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_page_escapes_provider_values() {
+        let pages = CallbackPages::new("https://auth.example.com/?a=1&b=2");
+        let page = pages.error("<script>alert('x')</script>");
+
+        assert!(!page.contains("<script>alert"));
+        assert!(page.contains("&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt;"));
+        assert!(page.contains("https://auth.example.com/?a=1&amp;b=2"));
+    }
+
+    #[test]
+    fn test_custom_template_and_redirect() {
+        let pages = CallbackPages {
+            success_template: Some(
+                "<body><h1>Welcome {{ user }}</h1>{{unknown}} {{error}}</body>".to_string(),
+            ),
+            success_redirect_url: Some("https://portal.example.com/?from=\"mcp\"".to_string()),
+            ..CallbackPages::new("https://auth.example.com")
+        };
+
+        let page = pages.success(Some("{{issuer}}"));
+        // Values are not re-scanned for placeholders
+        assert!(page.contains("Welcome {{issuer}}"));
+        assert!(page.contains("{{unknown}}"));
+        assert!(page.contains(
+            r#"<meta http-equiv="refresh" content="2;url=https://portal.example.com/?from=&quot;mcp&quot;"></body>"#
+        ));
+    }
+
+    #[test]
+    fn test_auto_close_script() {
+        let pages = CallbackPages {
+            auto_close: true,
+            ..CallbackPages::new("https://auth.example.com")
+        };
+
+        assert!(pages.success(None).contains("window.close()"));
+        assert!(pages.success(None).contains("your account"));
+        assert!(!pages.error("denied").contains("window.close()"));
+    }
+}
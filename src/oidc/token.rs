@@ -3,12 +3,155 @@
 //! Handles OAuth token storage, validation, and disk persistence.
 //! Compatible with Python version's token format for seamless migration.
 
+use crate::config::Config;
 use crate::error::{ProxyError, Result};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::path::PathBuf;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-const TOKEN_EXPIRY_BUFFER_SECS: u64 = 60;
+pub(crate) const TOKEN_EXPIRY_BUFFER_SECS: u64 = 60;
+
+#[cfg(test)]
+thread_local! {
+    /// Token storage of the running test instead of the user's (see `TempStorage`)
+    static TEST_STORAGE_DIR: std::cell::RefCell<Option<PathBuf>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// Temporary token storage for the current test thread, removed on drop
+#[cfg(test)]
+pub(crate) struct TempStorage(PathBuf);
+
+#[cfg(test)]
+impl TempStorage {
+    pub(crate) fn new() -> Self {
+        use rand::Rng;
+
+        let dir = std::env::temp_dir().join(format!(
+            "authful-mcp-proxy-tokens-{:016x}",
+            rand::thread_rng().gen::<u64>()
+        ));
+        TEST_STORAGE_DIR.with(|storage| *storage.borrow_mut() = Some(dir.clone()));
+        Self(dir)
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempStorage {
+    fn drop(&mut self) {
+        TEST_STORAGE_DIR.with(|storage| *storage.borrow_mut() = None);
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Key identifying one entry in the token cache
+///
+/// Tokens are cached per issuer, client, scope set and protected resource
+/// (the backend URL), so that different backends or clients against the same
+/// issuer never overwrite each other's tokens. An optional named profile allows
+/// holding several identities (e.g. personal vs. admin account) side by side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenCacheKey {
+    pub issuer: String,
+    pub client_id: String,
+    pub scopes: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Audience and resource of a token obtained by token exchange
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exchanged_for: Option<String>,
+}
+
+impl TokenCacheKey {
+    /// Create a cache key (scopes are normalized so their order doesn't matter)
+    pub fn new(issuer: &str, client_id: &str, scopes: &[String], resource: Option<&str>) -> Self {
+        let mut scopes = scopes.to_vec();
+        scopes.sort();
+        scopes.dedup();
+
+        Self {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            client_id: client_id.to_string(),
+            scopes,
+            resource: resource.map(|r| r.trim_end_matches('/').to_string()),
+            profile: None,
+            exchanged_for: None,
+        }
+    }
+
+    /// Cache key for the backend, client and profile of a proxy configuration
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(
+            &config.oidc_issuer_url,
+            &config.oidc_client_id,
+            &config.scopes(),
+            Some(&config.backend_url),
+        )
+        .with_profile(config.profile.clone())
+    }
+
+    /// Set the named profile this key belongs to
+    pub fn with_profile(mut self, profile: Option<String>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Key of the token exchanged for an audience, next to this key's token
+    pub fn exchanged_for(mut self, audience: &str, scopes: &[String]) -> Self {
+        let mut scopes = scopes.to_vec();
+        scopes.sort();
+        scopes.dedup();
+        self.scopes = scopes;
+        self.exchanged_for = Some(audience.to_string());
+        self
+    }
+
+    /// Short stable hash over all key components
+    fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.issuer.as_bytes());
+        hasher.update(b"\n");
+        hasher.update(self.client_id.as_bytes());
+        hasher.update(b"\n");
+        hasher.update(self.scopes.join(" ").as_bytes());
+        hasher.update(b"\n");
+        hasher.update(self.resource.as_deref().unwrap_or_default().as_bytes());
+        hasher.update(b"\n");
+        hasher.update(self.profile.as_deref().unwrap_or_default().as_bytes());
+        // Only hashed when set, so keys of login tokens remain unchanged
+        if let Some(ref audience) = self.exchanged_for {
+            hasher.update(b"\n");
+            hasher.update(audience.as_bytes());
+        }
+
+        hasher
+            .finalize()
+            .iter()
+            .take(8)
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Token file name for this key
+    ///
+    /// Example: auth.example.com_realms_myrealm_1a2b3c4d5e6f7a8b_tokens.json
+    ///          auth.example.com_realms_myrealm_admin_1a2b3c4d5e6f7a8b_tokens.json
+    fn file_name(&self) -> String {
+        let sanitized_issuer = TokenInfo::sanitize_issuer(&self.issuer);
+        match self.profile {
+            Some(ref profile) => format!(
+                "{}_{}_{}_tokens.json",
+                sanitized_issuer,
+                profile,
+                self.fingerprint()
+            ),
+            None => format!("{}_{}_tokens.json", sanitized_issuer, self.fingerprint()),
+        }
+    }
+}
 
 /// OAuth token information
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,10 +165,20 @@ pub struct TokenInfo {
     pub token_type: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub scope: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+
+    /// Cache entry these tokens were stored under (absent in legacy token files)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_key: Option<TokenCacheKey>,
 
-    /// Internal field: Unix timestamp when token expires
-    #[serde(skip)]
+    /// Unix timestamp when token expires (persisted so expiry survives restarts)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     expires_at: Option<u64>,
+
+    /// Whether the expiry was learned by token introspection
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    introspected: bool,
 }
 
 /// Token response from OIDC provider
@@ -40,6 +193,8 @@ pub struct TokenResponse {
     pub token_type: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub scope: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
 }
 
 impl From<TokenResponse> for TokenInfo {
@@ -58,7 +213,10 @@ impl From<TokenResponse> for TokenInfo {
             expires_in: response.expires_in,
             token_type: response.token_type,
             scope: response.scope,
+            id_token: response.id_token,
+            cache_key: None,
             expires_at,
+            introspected: false,
         }
     }
 }
@@ -83,6 +241,80 @@ impl TokenInfo {
         }
     }
 
+    /// Unix timestamp when the access token expires, if known
+    pub fn expires_at(&self) -> Option<u64> {
+        self.expires_at
+    }
+
+    /// Signed-in user for display, from the ID token (or a JWT access token)
+    ///
+    /// Prefers `preferred_username`, then `email`, `name` and `sub`.
+    pub fn user(&self) -> Option<String> {
+        let claims = self
+            .id_token
+            .as_deref()
+            .and_then(super::jwt::decode_claims)
+            .or_else(|| super::jwt::decode_claims(&self.access_token))?;
+
+        ["preferred_username", "email", "name", "sub"]
+            .iter()
+            .find_map(|claim| claims.get(*claim)?.as_str().map(String::from))
+    }
+
+    /// Signed-in user's identity from the ID token (or a JWT access token)
+    pub fn identity(&self) -> Option<super::userinfo::UserIdentity> {
+        self.id_token
+            .as_deref()
+            .and_then(super::jwt::decode_claims)
+            .or_else(|| super::jwt::decode_claims(&self.access_token))
+            .and_then(|claims| super::userinfo::UserIdentity::from_claims(&claims))
+    }
+
+    /// Claims of the ID token, overridden by those of a JWT access token
+    ///
+    /// Used for header templates; the claims are not verified.
+    pub fn claims(&self) -> serde_json::Map<String, serde_json::Value> {
+        let mut claims = self
+            .id_token
+            .as_deref()
+            .and_then(super::jwt::decode_claims)
+            .unwrap_or_default();
+        claims.extend(super::jwt::decode_claims(&self.access_token).unwrap_or_default());
+        claims
+    }
+
+    /// Check if the token's expiry can only be learned by introspection
+    ///
+    /// True for opaque access tokens issued without `expires_in`.
+    pub fn needs_introspection(&self) -> bool {
+        self.expires_at.is_none()
+            && !self.introspected
+            && !self.access_token.is_empty()
+            && !super::jwt::is_jwt(&self.access_token)
+    }
+
+    /// Store the expiry and scope learned by introspection (RFC 7662)
+    ///
+    /// Inactive tokens are marked as expired.
+    pub fn apply_introspection(&mut self, response: &super::introspection::IntrospectionResponse) {
+        self.introspected = true;
+        self.expires_at = if response.active {
+            response.exp
+        } else {
+            Some(0)
+        };
+        if self.scope.is_none() {
+            self.scope = response.scope.clone();
+        }
+    }
+
+    /// Check if the access token is bound to a DPoP key (RFC 9449)
+    pub fn is_dpop_bound(&self) -> bool {
+        self.token_type
+            .as_deref()
+            .is_some_and(|t| t.eq_ignore_ascii_case("dpop"))
+    }
+
     /// Check if token can be refreshed
     pub fn can_refresh(&self) -> bool {
         self.refresh_token.is_some()
@@ -92,7 +324,13 @@ impl TokenInfo {
     ///
     /// Returns: ~/.mcp/authful_mcp_proxy/tokens/ on Linux/macOS
     ///          %USERPROFILE%\.mcp\authful_mcp_proxy\tokens\ on Windows
-    fn get_storage_dir() -> Result<PathBuf> {
+    pub(crate) fn get_storage_dir() -> Result<PathBuf> {
+        #[cfg(test)]
+        if let Some(dir) = TEST_STORAGE_DIR.with(|storage| storage.borrow().clone()) {
+            std::fs::create_dir_all(&dir)?;
+            return Ok(dir);
+        }
+
         let home = std::env::var("HOME")
             .or_else(|_| std::env::var("USERPROFILE"))
             .map_err(|_| ProxyError::Token("Cannot determine home directory".to_string()))?;
@@ -119,29 +357,68 @@ impl TokenInfo {
             .replace(['/', ':'], "_")
     }
 
-    /// Get token file path for a given issuer
-    fn get_token_file_path(issuer_url: &str) -> Result<PathBuf> {
+    /// Get token file path for a given cache key
+    pub fn get_token_file_path(key: &TokenCacheKey) -> Result<PathBuf> {
         let storage_dir = Self::get_storage_dir()?;
-        let sanitized_issuer = Self::sanitize_issuer(issuer_url);
+        Ok(storage_dir.join(key.file_name()))
+    }
+
+    /// Get the legacy (issuer-only) token file path used by older versions
+    /// and the Python implementation
+    fn get_legacy_token_file_path(issuer_url: &str) -> Result<PathBuf> {
+        let storage_dir = Self::get_storage_dir()?;
+        let sanitized_issuer = Self::sanitize_issuer(issuer_url.trim_end_matches('/'));
         let filename = format!("{}_tokens.json", sanitized_issuer);
 
         Ok(storage_dir.join(filename))
     }
 
     /// Save tokens to disk
-    pub fn save_to_disk(&self, issuer_url: &str) -> Result<()> {
-        let file_path = Self::get_token_file_path(issuer_url)?;
+    pub fn save_to_disk(&self, key: &TokenCacheKey) -> Result<()> {
+        let file_path = Self::get_token_file_path(key)?;
 
-        let json = serde_json::to_string_pretty(self)?;
+        let entry = TokenInfo {
+            cache_key: Some(key.clone()),
+            ..self.clone()
+        };
+        let json = serde_json::to_string_pretty(&entry)?;
         std::fs::write(&file_path, json)?;
 
+        // Tokens are credentials: keep them private to the current user
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&file_path, std::fs::Permissions::from_mode(0o600))?;
+        }
+
         tracing::debug!("Tokens saved to {:?}", file_path);
         Ok(())
     }
 
     /// Load tokens from disk
-    pub fn load_from_disk(issuer_url: &str) -> Result<Option<Self>> {
-        let file_path = Self::get_token_file_path(issuer_url)?;
+    ///
+    /// When no entry exists for the key yet (default profile only), the legacy
+    /// issuer-only token file is migrated to it, so an existing login keeps
+    /// working. The legacy file is removed afterwards: it doesn't record the
+    /// client or backend it belongs to, so other keys must not pick it up too.
+    /// It only ever holds login tokens, never exchanged ones.
+    pub fn load_from_disk(key: &TokenCacheKey) -> Result<Option<Self>> {
+        let file_path = Self::get_token_file_path(key)?;
+
+        if !file_path.exists() && key.profile.is_none() && key.exchanged_for.is_none() {
+            let legacy_path = Self::get_legacy_token_file_path(&key.issuer)?;
+            if legacy_path.exists() {
+                tracing::info!(
+                    "Migrating legacy token cache {:?} to {:?}",
+                    legacy_path,
+                    file_path
+                );
+                let contents = std::fs::read_to_string(&legacy_path)?;
+                let token_info: TokenInfo = serde_json::from_str(&contents)?;
+                token_info.save_to_disk(key)?;
+                std::fs::remove_file(&legacy_path)?;
+            }
+        }
 
         if !file_path.exists() {
             tracing::debug!("No cached tokens found at {:?}", file_path);
@@ -151,8 +428,8 @@ impl TokenInfo {
         let contents = std::fs::read_to_string(&file_path)?;
         let mut token_info: TokenInfo = serde_json::from_str(&contents)?;
 
-        // Recompute expires_at from expires_in if present
-        if let Some(expires_in) = token_info.expires_in {
+        // Legacy token files don't record expires_at: recompute it from expires_in
+        if let (None, Some(expires_in)) = (token_info.expires_at, token_info.expires_in) {
             // Since we don't know when the token was originally created,
             // we can't accurately compute expires_at from a saved token.
             // The is_valid() check will conservatively treat it as expired
@@ -176,6 +453,58 @@ impl TokenInfo {
         tracing::debug!("Tokens loaded from {:?}", file_path);
         Ok(Some(token_info))
     }
+
+    /// Remove cached tokens for a key from disk
+    ///
+    /// Also removes the legacy issuer-only file for the default profile, since
+    /// `load_from_disk` would otherwise pick it up again.
+    pub fn remove_from_disk(key: &TokenCacheKey) -> Result<bool> {
+        let mut paths = vec![Self::get_token_file_path(key)?];
+        if key.profile.is_none() {
+            paths.push(Self::get_legacy_token_file_path(&key.issuer)?);
+        }
+
+        let mut removed = false;
+        for path in paths {
+            if path.exists() {
+                std::fs::remove_file(&path)?;
+                tracing::debug!("Removed cached tokens at {:?}", path);
+                removed = true;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// List all token cache entries on disk, including legacy ones
+    ///
+    /// Files that cannot be parsed are skipped with a warning.
+    pub fn list_cached() -> Result<Vec<(PathBuf, TokenInfo)>> {
+        let storage_dir = Self::get_storage_dir()?;
+
+        let mut entries = Vec::new();
+        for dir_entry in std::fs::read_dir(&storage_dir)? {
+            let path = dir_entry?.path();
+            let is_token_file = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.ends_with("_tokens.json"));
+            if !is_token_file {
+                continue;
+            }
+
+            let parsed = std::fs::read_to_string(&path)
+                .map_err(ProxyError::from)
+                .and_then(|contents| Ok(serde_json::from_str::<TokenInfo>(&contents)?));
+            match parsed {
+                Ok(token_info) => entries.push((path, token_info)),
+                Err(e) => tracing::warn!("Skipping unreadable token file {:?}: {}", path, e),
+            }
+        }
+
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(entries)
+    }
 }
 
 #[cfg(test)]
@@ -194,6 +523,107 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_cache_key_separates_clients_and_resources() {
+        let scopes = vec!["openid".to_string(), "profile".to_string()];
+        let base = TokenCacheKey::new(
+            "https://auth.example.com",
+            "client-a",
+            &scopes,
+            Some("https://mcp-a.example.com"),
+        );
+
+        let other_client = TokenCacheKey::new(
+            "https://auth.example.com",
+            "client-b",
+            &scopes,
+            Some("https://mcp-a.example.com"),
+        );
+        let other_resource = TokenCacheKey::new(
+            "https://auth.example.com",
+            "client-a",
+            &scopes,
+            Some("https://mcp-b.example.com"),
+        );
+        let other_profile = base.clone().with_profile(Some("admin".to_string()));
+
+        assert_ne!(base.file_name(), other_client.file_name());
+        assert_ne!(base.file_name(), other_resource.file_name());
+        assert_ne!(base.file_name(), other_profile.file_name());
+        assert!(base.file_name().starts_with("auth.example.com_"));
+        assert!(other_profile
+            .file_name()
+            .starts_with("auth.example.com_admin_"));
+    }
+
+    #[test]
+    fn test_cache_key_normalizes_scopes() {
+        let a = TokenCacheKey::new(
+            "https://auth.example.com/",
+            "client",
+            &["openid".to_string(), "email".to_string()],
+            None,
+        );
+        let b = TokenCacheKey::new(
+            "https://auth.example.com",
+            "client",
+            &["email".to_string(), "openid".to_string()],
+            None,
+        );
+
+        assert_eq!(a, b);
+        assert_eq!(a.file_name(), b.file_name());
+    }
+
+    #[test]
+    fn test_legacy_token_file_is_migrated_once() {
+        let _storage = TempStorage::new();
+        let issuer = "https://auth.example.com";
+        let legacy_path = TokenInfo::get_legacy_token_file_path(issuer).unwrap();
+        std::fs::write(
+            &legacy_path,
+            r#"{"access_token": "legacy", "refresh_token": "refresh", "expires_in": 3600}"#,
+        )
+        .unwrap();
+
+        let scopes = vec!["openid".to_string()];
+        let first = TokenCacheKey::new(issuer, "client-a", &scopes, None);
+        let second = TokenCacheKey::new(issuer, "client-b", &scopes, None);
+
+        // The first key takes over the legacy login
+        let migrated = TokenInfo::load_from_disk(&first).unwrap().unwrap();
+        assert_eq!(migrated.access_token, "legacy");
+        assert!(!legacy_path.exists());
+        assert!(TokenInfo::get_token_file_path(&first).unwrap().exists());
+        assert_eq!(
+            TokenInfo::load_from_disk(&first)
+                .unwrap()
+                .unwrap()
+                .access_token,
+            "legacy"
+        );
+
+        // Other clients of the issuer have to log in themselves
+        assert!(TokenInfo::load_from_disk(&second).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_legacy_token_file_is_not_an_exchanged_token() {
+        let _storage = TempStorage::new();
+        let issuer = "https://auth.example.com";
+        let legacy_path = TokenInfo::get_legacy_token_file_path(issuer).unwrap();
+        std::fs::write(
+            &legacy_path,
+            r#"{"access_token": "login-token", "expires_in": 3600}"#,
+        )
+        .unwrap();
+
+        let key = TokenCacheKey::new(issuer, "client-a", &["openid".to_string()], None)
+            .exchanged_for("gateway", &[]);
+        assert!(TokenInfo::load_from_disk(&key).unwrap().is_none());
+        assert!(legacy_path.exists());
+    }
+
     #[test]
     fn test_token_validation() {
         // Valid token
@@ -203,6 +633,8 @@ mod tests {
             expires_in: Some(3600),
             token_type: Some("Bearer".to_string()),
             scope: None,
+            id_token: None,
+            cache_key: None,
             expires_at: Some(
                 SystemTime::now()
                     .duration_since(UNIX_EPOCH)
@@ -210,6 +642,7 @@ mod tests {
                     .as_secs()
                     + 3600,
             ),
+            introspected: false,
         };
 
         assert!(token.is_valid());
@@ -232,7 +665,10 @@ mod tests {
             expires_in: None,
             token_type: None,
             scope: None,
+            id_token: None,
+            cache_key: None,
             expires_at: None,
+            introspected: false,
         };
 
         assert!(!empty_token.is_valid());
@@ -246,7 +682,10 @@ mod tests {
             expires_in: None,
             token_type: None,
             scope: None,
+            id_token: None,
+            cache_key: None,
             expires_at: None,
+            introspected: false,
         };
 
         assert!(token_with_refresh.can_refresh());
@@ -257,7 +696,10 @@ mod tests {
             expires_in: None,
             token_type: None,
             scope: None,
+            id_token: None,
+            cache_key: None,
             expires_at: None,
+            introspected: false,
         };
 
         assert!(!token_without_refresh.can_refresh());
@@ -0,0 +1,151 @@
+//! Signed-in identity
+//!
+//! Determines which account the proxy acts as, from the provider's userinfo
+//! endpoint or, without network access, from the ID token claims. Used for
+//! the startup banner, `status` and the MCP log message sent to the client.
+
+use crate::error::{ProxyError, Result};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Identity of the signed-in user
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct UserIdentity {
+    pub subject: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+impl UserIdentity {
+    /// Identity from OIDC claims (`sub` is required)
+    pub fn from_claims(claims: &Map<String, Value>) -> Option<Self> {
+        let claim = |name: &str| {
+            claims
+                .get(name)
+                .and_then(Value::as_str)
+                .filter(|value| !value.is_empty())
+                .map(String::from)
+        };
+
+        Some(Self {
+            subject: claim("sub")?,
+            name: claim("name"),
+            username: claim("preferred_username"),
+            email: claim("email"),
+        })
+    }
+}
+
+impl fmt::Display for UserIdentity {
+    /// "Jane Doe <jane@example.com>", else the first of username, email, name and subject
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.name, &self.email) {
+            (Some(name), Some(email)) => write!(f, "{} <{}>", name, email),
+            _ => f.write_str(
+                self.username
+                    .as_deref()
+                    .or(self.email.as_deref())
+                    .or(self.name.as_deref())
+                    .unwrap_or(&self.subject),
+            ),
+        }
+    }
+}
+
+/// Fetch the claims of the token's user from the userinfo endpoint
+///
+/// `authorization` is the complete `Authorization` header value; `dpop_proof`
+/// is sent along for DPoP-bound tokens.
+pub async fn fetch(
+    http: &reqwest::Client,
+    userinfo_endpoint: &str,
+    authorization: &str,
+    dpop_proof: Option<String>,
+) -> Result<UserIdentity> {
+    let mut request = http
+        .get(userinfo_endpoint)
+        .header(reqwest::header::AUTHORIZATION, authorization)
+        .header(reqwest::header::ACCEPT, "application/json")
+        .timeout(std::time::Duration::from_secs(5));
+    if let Some(proof) = dpop_proof {
+        request = request.header(super::dpop::DPOP_HEADER, proof);
+    }
+
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        return Err(ProxyError::Auth(format!(
+            "Userinfo request failed with status: {}",
+            response.status()
+        )));
+    }
+
+    // Providers returning signed userinfo (application/jwt) are not supported
+    let claims: Map<String, Value> = response
+        .json()
+        .await
+        .map_err(|e| ProxyError::Auth(format!("Failed to parse userinfo response: {}", e)))?;
+
+    UserIdentity::from_claims(&claims)
+        .ok_or_else(|| ProxyError::Auth("Userinfo response has no 'sub' claim".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(claims) => claims,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_display_identity() {
+        let full = UserIdentity::from_claims(&claims(serde_json::json!({
+            "sub": "248289761001",
+            "name": "Jane Doe",
+            "preferred_username": "jane",
+            "email": "jane@example.com",
+        })))
+        .unwrap();
+        assert_eq!(full.to_string(), "Jane Doe <jane@example.com>");
+
+        let minimal = UserIdentity::from_claims(&claims(
+            serde_json::json!({"sub": "248289761001", "email": ""}),
+        ))
+        .unwrap();
+        assert_eq!(minimal.to_string(), "248289761001");
+
+        assert!(UserIdentity::from_claims(&claims(serde_json::json!({"name": "Jane"}))).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_userinfo() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/userinfo")
+            .match_header("authorization", "Bearer token-1")
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"sub": "u1", "preferred_username": "jane"}"#)
+            .create_async()
+            .await;
+
+        let identity = fetch(
+            &reqwest::Client::new(),
+            &format!("{}/userinfo", server.url()),
+            "Bearer token-1",
+            None,
+        )
+        .await
+        .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(identity.to_string(), "jane");
+    }
+}
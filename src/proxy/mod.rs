@@ -1,5 +1,6 @@
 //! MCP proxy module
 
 pub mod server;
+pub mod timeout;
 
 pub use server::run_proxy_server;
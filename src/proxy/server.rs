@@ -3,15 +3,25 @@
 //! Bridges stdio transport (for MCP clients like Claude Desktop) to HTTP transport
 //! (for remote MCP servers with OIDC authentication).
 
+use crate::auth::AuthProvider;
 use crate::config::Config;
 use crate::error::{ProxyError, Result};
-use crate::middleware::AuthMiddleware;
-use crate::oidc::OidcClient;
-use reqwest_middleware::ClientBuilder;
+use crate::http::{HttpClientFactory, Target};
+use crate::middleware::{AuthMiddleware, HeaderMiddleware, RetryMiddleware, SigV4Middleware};
+use crate::oidc::userinfo::UserIdentity;
+use crate::proxy::timeout::RequestTimeouts;
+use crate::retry::RetryPolicy;
+use crate::sigv4::SigV4Signer;
+use reqwest::StatusCode;
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
+use serde_json::Value;
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 use tokio::fs::OpenOptions;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::{AbortHandle, JoinSet};
 
 /// Message logger for debugging
 struct MessageLogger {
@@ -93,6 +103,308 @@ impl MessageLogger {
     }
 }
 
+/// MCP log notification telling the client which account the tools run as
+fn identity_notification(identity: &UserIdentity) -> Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/message",
+        "params": {
+            "level": "info",
+            "logger": "authful-mcp-proxy",
+            "data": {
+                "message": format!("Signed in as {}", identity),
+                "user": identity,
+            }
+        }
+    })
+}
+
+/// Whether an `initialize` response declares the `logging` capability
+///
+/// The response is either plain JSON or an SSE stream with the result in a
+/// `data:` line.
+fn declares_logging(response_body: &str) -> bool {
+    std::iter::once(response_body)
+        .chain(
+            response_body
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:")),
+        )
+        .filter_map(|message| serde_json::from_str::<Value>(message.trim()).ok())
+        .any(|message| message.pointer("/result/capabilities/logging").is_some())
+}
+
+/// JSON-RPC error code for requests the backend did not answer in time
+const REQUEST_TIMEOUT: i64 = -32001;
+
+/// JSON-RPC error response for the client
+fn error_response(id: Option<&Value>, code: i64, message: String) -> Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "error": {
+            "code": code,
+            "message": message
+        },
+        "id": id.cloned().unwrap_or(Value::Null)
+    })
+}
+
+/// Whether a failed request ran into its timeout
+fn is_timeout(error: &ProxyError) -> bool {
+    match error {
+        ProxyError::Http(e) => e.is_timeout(),
+        ProxyError::Middleware(reqwest_middleware::Error::Reqwest(e)) => e.is_timeout(),
+        _ => false,
+    }
+}
+
+/// Forwards single messages to the backend and their responses to the client
+struct Forwarder {
+    backend_url: String,
+    http_client: ClientWithMiddleware,
+    auth: Arc<dyn AuthProvider>,
+    timeouts: RequestTimeouts,
+    message_logger: Arc<Mutex<MessageLogger>>,
+    /// Lines for the client, written to stdout in order by the writer task
+    output: mpsc::UnboundedSender<String>,
+}
+
+impl Forwarder {
+    /// Forward a message and deliver the backend's response
+    async fn forward(&self, message: String, id: Option<Value>, method: Option<String>) {
+        let is_initialize = method.as_deref() == Some("initialize");
+
+        // Only requests (with an id) time out; notifications have no response
+        let timeout = method
+            .as_deref()
+            .filter(|_| id.is_some())
+            .and_then(|method| self.timeouts.for_method(method));
+
+        match self.post(message, timeout).await {
+            Ok((status, response_body)) => {
+                tracing::debug!("Received from backend: {}", response_body);
+
+                // Log backend response
+                if let Err(e) = self
+                    .message_logger
+                    .lock()
+                    .await
+                    .log_backend_response(&response_body)
+                    .await
+                {
+                    tracing::warn!("{}", e);
+                }
+
+                // Logging is allowed right after initialization, if the
+                // backend declared the capability
+                let notify_identity =
+                    is_initialize && status.is_success() && declares_logging(&response_body);
+                self.send(response_body);
+
+                if notify_identity {
+                    if let Some(identity) = self.auth.identity().await {
+                        self.send(identity_notification(&identity).to_string());
+                    }
+                }
+            }
+            Err(e) if is_timeout(&e) => {
+                let seconds = timeout.unwrap_or_default().as_secs();
+                let request_id = id.clone().unwrap_or_default();
+                tracing::warn!(
+                    "Request {} ({}) timed out after {} seconds",
+                    request_id,
+                    method.as_deref().unwrap_or_default(),
+                    seconds
+                );
+                self.send(
+                    error_response(
+                        id.as_ref(),
+                        REQUEST_TIMEOUT,
+                        format!("Request timed out after {} seconds", seconds),
+                    )
+                    .to_string(),
+                );
+
+                // Let the backend stop working on it
+                if let Some(id) = id {
+                    self.cancel_at_backend(id, "Request timed out").await;
+                }
+            }
+            Err(e @ ProxyError::Http(_)) => {
+                tracing::error!("Failed to read backend response body: {}", e);
+                self.send(
+                    error_response(id.as_ref(), -32603, format!("Backend error: {}", e))
+                        .to_string(),
+                );
+            }
+            Err(e) => {
+                tracing::error!("Failed to forward request to backend: {}", e);
+                self.send(
+                    error_response(id.as_ref(), -32603, format!("Proxy error: {}", e)).to_string(),
+                );
+            }
+        }
+    }
+
+    /// POST a message to the backend and read the complete response
+    async fn post(
+        &self,
+        message: String,
+        timeout: Option<Duration>,
+    ) -> Result<(StatusCode, String)> {
+        // Accept both JSON and SSE for compatibility with different MCP server implementations
+        let mut request = self
+            .http_client
+            .post(&self.backend_url)
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json, text/event-stream")
+            .body(message);
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        tracing::debug!("Backend response status: {}", status);
+
+        Ok((status, response.text().await?))
+    }
+
+    /// Send `notifications/cancelled` for a request to the backend
+    async fn cancel_at_backend(&self, id: Value, reason: &str) {
+        let notification = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/cancelled",
+            "params": {
+                "requestId": id,
+                "reason": reason
+            }
+        });
+        if let Err(e) = self.post(notification.to_string(), None).await {
+            tracing::debug!("Failed to forward cancellation to backend: {}", e);
+        }
+    }
+
+    /// Queue a line for the client
+    fn send(&self, line: String) {
+        // Fails only after the writer stopped, which ends the proxy anyway
+        let _ = self.output.send(line);
+    }
+}
+
+/// How long pending requests may take after the client disconnected
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// Forwards client messages concurrently and tracks the pending requests
+struct Dispatcher {
+    forwarder: Arc<Forwarder>,
+    tasks: JoinSet<()>,
+    /// Requests being forwarded, by JSON-encoded id (for cancellation)
+    in_flight: HashMap<String, AbortHandle>,
+}
+
+impl Dispatcher {
+    fn new(forwarder: Arc<Forwarder>) -> Self {
+        Self {
+            forwarder,
+            tasks: JoinSet::new(),
+            in_flight: HashMap::new(),
+        }
+    }
+
+    /// Forward a message from the client
+    ///
+    /// Requests are forwarded in the background. Notifications are forwarded
+    /// before returning, so they reach the backend in the order the client
+    /// sent them, and before any message sent after them.
+    async fn dispatch(&mut self, message: &str) {
+        while self.tasks.try_join_next().is_some() {}
+        self.in_flight.retain(|_, task| !task.is_finished());
+
+        // Validate JSON-RPC format
+        let request = match serde_json::from_str::<Value>(message) {
+            Ok(request) => request,
+            Err(e) => {
+                tracing::warn!("Invalid JSON received: {}", e);
+                return;
+            }
+        };
+        let method = request
+            .get("method")
+            .and_then(Value::as_str)
+            .map(String::from);
+        let id = request.get("id").filter(|id| !id.is_null()).cloned();
+
+        // Abort the HTTP request of a cancelled request (the notification is
+        // forwarded below, so the backend stops as well)
+        if method.as_deref() == Some("notifications/cancelled") {
+            if let Some(request_id) = request.pointer("/params/requestId") {
+                if let Some(task) = self.in_flight.remove(&request_id.to_string()) {
+                    task.abort();
+                    tracing::info!("Cancelled request {}", request_id);
+                }
+            }
+        }
+
+        let Some(id) = id else {
+            self.forwarder
+                .forward(message.to_string(), None, method)
+                .await;
+            return;
+        };
+        let task = self.tasks.spawn({
+            let forwarder = self.forwarder.clone();
+            let message = message.to_string();
+            let id = id.clone();
+            async move { forwarder.forward(message, Some(id), method).await }
+        });
+        self.in_flight.insert(id.to_string(), task);
+    }
+
+    /// Let pending requests finish within `grace`, then abort the rest
+    async fn shutdown(mut self, grace: Duration) {
+        let pending = async { while self.tasks.join_next().await.is_some() {} };
+        if tokio::time::timeout(grace, pending).await.is_err() {
+            tracing::warn!(
+                "Aborting {} pending request(s) after the client disconnected",
+                self.tasks.len()
+            );
+            self.tasks.shutdown().await;
+        }
+    }
+}
+
+/// Write lines for the client to stdout until all senders are gone
+async fn write_output(
+    mut lines: mpsc::UnboundedReceiver<String>,
+    message_logger: Arc<Mutex<MessageLogger>>,
+) -> Result<()> {
+    let mut stdout = tokio::io::stdout();
+    while let Some(line) = lines.recv().await {
+        // Write response back to stdout (with newline for JSON-RPC)
+        stdout
+            .write_all(line.as_bytes())
+            .await
+            .map_err(|e| ProxyError::Mcp(format!("Failed to write to stdout: {}", e)))?;
+        stdout
+            .write_all(b"\n")
+            .await
+            .map_err(|e| ProxyError::Mcp(format!("Failed to write newline to stdout: {}", e)))?;
+        stdout
+            .flush()
+            .await
+            .map_err(|e| ProxyError::Mcp(format!("Failed to flush stdout: {}", e)))?;
+
+        // Log what we sent to client
+        message_logger
+            .lock()
+            .await
+            .log_client_response(&line)
+            .await?;
+    }
+    Ok(())
+}
+
 /// Run the MCP proxy server
 ///
 /// This function sets up a bidirectional bridge between:
@@ -100,27 +412,61 @@ impl MessageLogger {
 /// - Backend: HTTP with OIDC authentication (for remote MCP servers)
 ///
 /// MCP messages are JSON-RPC formatted and forwarded transparently between
-/// both transports. The OIDC middleware automatically injects bearer tokens
-/// and handles 401 responses with token refresh.
-pub async fn run_proxy_server(config: Config, oidc_client: OidcClient) -> Result<()> {
+/// both transports. The auth middleware automatically injects the provider's
+/// credentials and handles 401 responses with token refresh. After a
+/// successful `initialize` declaring the `logging` capability, the client is
+/// told the signed-in identity (if the provider knows it) with a
+/// `notifications/message` log message.
+///
+/// Requests are forwarded concurrently, notifications in order (see
+/// `Dispatcher::dispatch`). A request that exceeds its timeout
+/// is answered with a JSON-RPC error carrying its id; on
+/// `notifications/cancelled` the HTTP request for that id is aborted, and in
+/// both cases the backend is sent the cancellation. When the client
+/// disconnects, pending requests get a few seconds to finish before they are
+/// aborted.
+pub async fn run_proxy_server(config: Config, auth: Arc<dyn AuthProvider>) -> Result<()> {
     tracing::info!("MCP proxy server starting...");
     tracing::info!("Backend URL: {}", config.backend_url);
 
     // Initialize message logger if enabled
-    let mut message_logger = MessageLogger::new(config.dump_messages.clone()).await?;
+    let message_logger = Arc::new(Mutex::new(
+        MessageLogger::new(config.dump_messages.clone()).await?,
+    ));
 
     // Create authenticated HTTP client with middleware
-    let auth_middleware = AuthMiddleware::new(Arc::new(oidc_client));
-    let http_client = ClientBuilder::new(reqwest::Client::new())
-        .with(auth_middleware)
-        .build();
+    let auth_middleware = AuthMiddleware::new(auth.clone());
+    let mut client_builder =
+        ClientBuilder::new(HttpClientFactory::from_config(&config)?.client(Target::Backend)?)
+            .with(RetryMiddleware::new(RetryPolicy::from_config(&config)))
+            .with(auth_middleware);
+    let headers = crate::headers::from_config(&config)?;
+    if !headers.is_empty() {
+        client_builder = client_builder.with(HeaderMiddleware::new(headers, auth.clone()));
+    }
+    if config.sigv4 {
+        client_builder =
+            client_builder.with(SigV4Middleware::new(SigV4Signer::from_config(&config)?));
+        tracing::info!("Signing backend requests with AWS SigV4");
+    }
+    let http_client = client_builder.build();
 
     tracing::info!("Authenticated HTTP client created");
 
     // Set up stdio transport (read from stdin, write to stdout)
     let stdin = tokio::io::stdin();
-    let mut stdout = tokio::io::stdout();
     let mut reader = BufReader::new(stdin);
+    let (output, lines) = mpsc::unbounded_channel();
+    let writer = tokio::spawn(write_output(lines, message_logger.clone()));
+
+    let forwarder = Arc::new(Forwarder {
+        backend_url: config.backend_url.clone(),
+        http_client,
+        auth,
+        timeouts: RequestTimeouts::from_config(&config)?,
+        message_logger: message_logger.clone(),
+        output,
+    });
 
     tracing::info!("MCP proxy server running on stdio transport");
     tracing::info!(
@@ -128,6 +474,8 @@ pub async fn run_proxy_server(config: Config, oidc_client: OidcClient) -> Result
         config.backend_url
     );
 
+    let mut dispatcher = Dispatcher::new(forwarder);
+
     // Message forwarding loop
     let mut line = String::new();
     loop {
@@ -145,6 +493,11 @@ pub async fn run_proxy_server(config: Config, oidc_client: OidcClient) -> Result
             break;
         }
 
+        // The writer only stops when stdout is gone
+        if writer.is_finished() {
+            break;
+        }
+
         let request_line = line.trim();
         if request_line.is_empty() {
             continue;
@@ -153,110 +506,309 @@ pub async fn run_proxy_server(config: Config, oidc_client: OidcClient) -> Result
         tracing::debug!("Received from client: {}", request_line);
 
         // Log client request
-        message_logger.log_client_request(request_line).await?;
+        message_logger
+            .lock()
+            .await
+            .log_client_request(request_line)
+            .await?;
 
-        // Validate JSON-RPC format
-        if let Err(e) = serde_json::from_str::<serde_json::Value>(request_line) {
-            tracing::warn!("Invalid JSON received: {}", e);
-            continue;
-        }
+        dispatcher.dispatch(request_line).await;
+    }
 
-        // Forward to backend HTTP server
-        // Accept both JSON and SSE for compatibility with different MCP server implementations
-        match http_client
-            .post(&config.backend_url)
-            .header("Content-Type", "application/json")
-            .header("Accept", "application/json, text/event-stream")
-            .body(request_line.to_string())
-            .send()
-            .await
-        {
-            Ok(response) => {
-                let status = response.status();
-                tracing::debug!("Backend response status: {}", status);
-
-                match response.text().await {
-                    Ok(response_body) => {
-                        tracing::debug!("Received from backend: {}", response_body);
-
-                        // Log backend response
-                        message_logger.log_backend_response(&response_body).await?;
-
-                        // Write response back to stdout (with newline for JSON-RPC)
-                        stdout
-                            .write_all(response_body.as_bytes())
-                            .await
-                            .map_err(|e| {
-                                ProxyError::Mcp(format!("Failed to write to stdout: {}", e))
-                            })?;
-                        stdout.write_all(b"\n").await.map_err(|e| {
-                            ProxyError::Mcp(format!("Failed to write newline to stdout: {}", e))
-                        })?;
-                        stdout.flush().await.map_err(|e| {
-                            ProxyError::Mcp(format!("Failed to flush stdout: {}", e))
-                        })?;
-
-                        // Log what we sent to client
-                        message_logger.log_client_response(&response_body).await?;
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to read backend response body: {}", e);
-                        // Send JSON-RPC error response
-                        let error_response = serde_json::json!({
-                            "jsonrpc": "2.0",
-                            "error": {
-                                "code": -32603,
-                                "message": format!("Backend error: {}", e)
-                            },
-                            "id": null
-                        });
-                        let error_str = error_response.to_string();
-
-                        stdout.write_all(error_str.as_bytes()).await.map_err(|e| {
-                            ProxyError::Mcp(format!("Failed to write error to stdout: {}", e))
-                        })?;
-                        stdout.write_all(b"\n").await.map_err(|e| {
-                            ProxyError::Mcp(format!("Failed to write newline to stdout: {}", e))
-                        })?;
-                        stdout.flush().await.map_err(|e| {
-                            ProxyError::Mcp(format!("Failed to flush stdout: {}", e))
-                        })?;
-
-                        // Log error response
-                        message_logger.log_client_response(&error_str).await?;
-                    }
-                }
+    // Give pending requests a moment to finish, then flush their responses
+    dispatcher.shutdown(SHUTDOWN_GRACE).await;
+    writer
+        .await
+        .map_err(|e| ProxyError::Mcp(format!("Output task failed: {}", e)))??;
+
+    tracing::info!("MCP proxy server stopped");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::NoAuth;
+    use mockito::Matcher;
+    use serde_json::json;
+    use std::time::Instant;
+
+    /// Forwarder to a mock backend, with the lines it sends to the client
+    async fn forwarder(
+        backend_url: String,
+        config: &Config,
+    ) -> (Arc<Forwarder>, mpsc::UnboundedReceiver<String>) {
+        let (output, lines) = mpsc::unbounded_channel();
+        let http_client = ClientBuilder::new(reqwest::Client::new())
+            .with(RetryMiddleware::new(RetryPolicy::from_config(config)))
+            .build();
+        let forwarder = Forwarder {
+            backend_url,
+            http_client,
+            auth: Arc::new(NoAuth),
+            timeouts: RequestTimeouts::from_config(config).unwrap(),
+            message_logger: Arc::new(Mutex::new(MessageLogger::new(None).await.unwrap())),
+            output,
+        };
+        (Arc::new(forwarder), lines)
+    }
+
+    /// Response body that arrives after `delay`
+    ///
+    /// Whitespace is written meanwhile, so the mock server notices an aborted
+    /// request (it waits for the body before serving other connections).
+    fn delayed(
+        body: Value,
+        delay: Duration,
+    ) -> impl Fn(&mut dyn std::io::Write) -> std::io::Result<()> {
+        move |writer| {
+            let started = Instant::now();
+            while started.elapsed() < delay {
+                writer.write_all(b" ")?;
+                std::thread::sleep(Duration::from_millis(50));
             }
-            Err(e) => {
-                tracing::error!("Failed to forward request to backend: {}", e);
-                // Send JSON-RPC error response
-                let error_response = serde_json::json!({
-                    "jsonrpc": "2.0",
-                    "error": {
-                        "code": -32603,
-                        "message": format!("Proxy error: {}", e)
-                    },
-                    "id": null
-                });
-                let error_str = error_response.to_string();
-
-                stdout.write_all(error_str.as_bytes()).await.map_err(|e| {
-                    ProxyError::Mcp(format!("Failed to write error to stdout: {}", e))
-                })?;
-                stdout.write_all(b"\n").await.map_err(|e| {
-                    ProxyError::Mcp(format!("Failed to write newline to stdout: {}", e))
-                })?;
-                stdout
-                    .flush()
-                    .await
-                    .map_err(|e| ProxyError::Mcp(format!("Failed to flush stdout: {}", e)))?;
+            writer.write_all(body.to_string().as_bytes())
+        }
+    }
 
-                // Log error response
-                message_logger.log_client_response(&error_str).await?;
+    /// Lines sent to the client, once all requests are done
+    async fn client_lines(mut lines: mpsc::UnboundedReceiver<String>) -> Vec<Value> {
+        let mut messages = Vec::new();
+        while let Some(line) = lines.recv().await {
+            if let Ok(message) = serde_json::from_str(&line) {
+                messages.push(message);
             }
         }
+        messages
     }
 
-    tracing::info!("MCP proxy server stopped");
-    Ok(())
+    #[tokio::test]
+    async fn test_timed_out_request_is_answered_and_cancelled() {
+        let mut server = mockito::Server::new_async().await;
+        // Not retried, although tools/list is safe to retry
+        let slow = server
+            .mock("POST", "/mcp")
+            .match_body(Matcher::PartialJson(json!({"method": "tools/list"})))
+            .with_header("content-type", "application/json")
+            .with_chunked_body(delayed(
+                json!({"jsonrpc": "2.0", "id": 7, "result": {"tools": []}}),
+                Duration::from_secs(3),
+            ))
+            .expect(1)
+            .create_async()
+            .await;
+        let cancel = server
+            .mock("POST", "/mcp")
+            .match_body(Matcher::PartialJson(json!({
+                "method": "notifications/cancelled",
+                "params": {"requestId": 7, "reason": "Request timed out"}
+            })))
+            .with_status(202)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let config = Config {
+            request_timeout: Some(1),
+            ..Default::default()
+        };
+        let (forwarder, lines) = forwarder(format!("{}/mcp", server.url()), &config).await;
+        let mut dispatcher = Dispatcher::new(forwarder);
+        dispatcher
+            .dispatch(r#"{"jsonrpc":"2.0","id":7,"method":"tools/list"}"#)
+            .await;
+        dispatcher.shutdown(Duration::from_secs(10)).await;
+
+        let messages = client_lines(lines).await;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["id"], 7);
+        assert_eq!(messages[0]["error"]["code"], REQUEST_TIMEOUT);
+        slow.assert_async().await;
+        cancel.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_request_is_aborted() {
+        let mut server = mockito::Server::new_async().await;
+        let _slow = server
+            .mock("POST", "/mcp")
+            .match_body(Matcher::PartialJson(json!({"method": "tools/call"})))
+            .with_header("content-type", "application/json")
+            .with_chunked_body(delayed(
+                json!({"jsonrpc": "2.0", "id": 3, "result": {}}),
+                Duration::from_secs(5),
+            ))
+            .create_async()
+            .await;
+        let cancel = server
+            .mock("POST", "/mcp")
+            .match_body(Matcher::PartialJson(json!({
+                "method": "notifications/cancelled",
+                "params": {"requestId": 3}
+            })))
+            .with_status(202)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let (forwarder, lines) =
+            forwarder(format!("{}/mcp", server.url()), &Config::default()).await;
+        let mut dispatcher = Dispatcher::new(forwarder);
+        let started = Instant::now();
+        dispatcher
+            .dispatch(r#"{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"slow"}}"#)
+            .await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        dispatcher
+            .dispatch(
+                r#"{"jsonrpc":"2.0","method":"notifications/cancelled","params":{"requestId":3}}"#,
+            )
+            .await;
+        dispatcher.shutdown(Duration::from_secs(10)).await;
+
+        // The request was abandoned without waiting for the backend
+        assert!(started.elapsed() < Duration::from_secs(5));
+        let messages = client_lines(lines).await;
+        assert!(messages.iter().all(|message| message["id"] != 3));
+        cancel.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_notifications_keep_their_order() {
+        let mut server = mockito::Server::new_async().await;
+        // Messages in the order the backend finished receiving them
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let _initialized = server
+            .mock("POST", "/mcp")
+            .match_body(Matcher::PartialJson(
+                json!({"method": "notifications/initialized"}),
+            ))
+            .with_status(202)
+            .with_chunked_body({
+                let received = received.clone();
+                move |_| {
+                    std::thread::sleep(Duration::from_millis(300));
+                    received.lock().unwrap().push("notifications/initialized");
+                    Ok(())
+                }
+            })
+            .create_async()
+            .await;
+        let _roots = server
+            .mock("POST", "/mcp")
+            .match_body(Matcher::PartialJson(
+                json!({"method": "notifications/roots/list_changed"}),
+            ))
+            .with_status(202)
+            .with_body_from_request({
+                let received = received.clone();
+                move |_| {
+                    received
+                        .lock()
+                        .unwrap()
+                        .push("notifications/roots/list_changed");
+                    Vec::new()
+                }
+            })
+            .create_async()
+            .await;
+        let _tools = server
+            .mock("POST", "/mcp")
+            .match_body(Matcher::PartialJson(json!({"method": "tools/list"})))
+            .with_header("content-type", "application/json")
+            .with_body_from_request({
+                let received = received.clone();
+                move |_| {
+                    received.lock().unwrap().push("tools/list");
+                    json!({"jsonrpc": "2.0", "id": 1, "result": {"tools": []}})
+                        .to_string()
+                        .into_bytes()
+                }
+            })
+            .create_async()
+            .await;
+
+        let (forwarder, lines) =
+            forwarder(format!("{}/mcp", server.url()), &Config::default()).await;
+        let mut dispatcher = Dispatcher::new(forwarder);
+        dispatcher
+            .dispatch(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#)
+            .await;
+        dispatcher
+            .dispatch(r#"{"jsonrpc":"2.0","method":"notifications/roots/list_changed"}"#)
+            .await;
+        dispatcher
+            .dispatch(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#)
+            .await;
+        dispatcher.shutdown(Duration::from_secs(10)).await;
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![
+                "notifications/initialized",
+                "notifications/roots/list_changed",
+                "tools/list"
+            ]
+        );
+        let messages = client_lines(lines).await;
+        assert_eq!(messages.last().unwrap()["id"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_responses_are_sent_as_they_complete() {
+        let mut server = mockito::Server::new_async().await;
+        let _slow = server
+            .mock("POST", "/mcp")
+            .match_body(Matcher::PartialJson(json!({"id": 1})))
+            .with_header("content-type", "application/json")
+            .with_chunked_body(delayed(
+                json!({"jsonrpc": "2.0", "id": 1, "result": {"slow": true}}),
+                Duration::from_millis(500),
+            ))
+            .create_async()
+            .await;
+        let _fast = server
+            .mock("POST", "/mcp")
+            .match_body(Matcher::PartialJson(json!({"id": 2})))
+            .with_header("content-type", "application/json")
+            .with_body(json!({"jsonrpc": "2.0", "id": 2, "result": {"slow": false}}).to_string())
+            .create_async()
+            .await;
+
+        let (forwarder, lines) =
+            forwarder(format!("{}/mcp", server.url()), &Config::default()).await;
+        let mut dispatcher = Dispatcher::new(forwarder);
+        dispatcher
+            .dispatch(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#)
+            .await;
+        dispatcher
+            .dispatch(r#"{"jsonrpc":"2.0","id":2,"method":"ping"}"#)
+            .await;
+        dispatcher.shutdown(Duration::from_secs(10)).await;
+
+        // The fast response is not held back by the slow one, and each
+        // response arrives whole with its own id
+        let messages = client_lines(lines).await;
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0]["id"], 2);
+        assert_eq!(messages[0]["result"]["slow"], false);
+        assert_eq!(messages[1]["id"], 1);
+        assert_eq!(messages[1]["result"]["slow"], true);
+    }
+
+    #[test]
+    fn test_declares_logging() {
+        let with_logging =
+            r#"{"jsonrpc":"2.0","id":1,"result":{"capabilities":{"logging":{},"tools":{}}}}"#;
+        assert!(declares_logging(with_logging));
+        assert!(declares_logging(&format!(
+            "event: message\ndata: {}\n\n",
+            with_logging
+        )));
+
+        assert!(!declares_logging(
+            r#"{"jsonrpc":"2.0","id":1,"result":{"capabilities":{"tools":{}}}}"#
+        ));
+        assert!(!declares_logging("not json"));
+    }
 }
@@ -0,0 +1,92 @@
+//! Per-method request timeouts
+//!
+//! `--request-timeout` applies to every request, `--method-timeout` overrides
+//! it for single methods (e.g. longer for `tools/call`). A timeout covers
+//! one attempt at the backend, from connecting until the response body
+//! (including an SSE stream) is complete; an interactive login in between
+//! does not count. A request that runs into its timeout is not retried.
+
+use crate::config::Config;
+use crate::error::{ProxyError, Result};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Timeouts of backend requests by JSON-RPC method
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestTimeouts {
+    default: Option<Duration>,
+    methods: HashMap<String, Duration>,
+}
+
+impl RequestTimeouts {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut methods = HashMap::new();
+        for entry in &config.method_timeouts {
+            let invalid = || {
+                ProxyError::Config(format!(
+                    "Invalid method timeout '{}': expected METHOD=SECONDS",
+                    entry
+                ))
+            };
+            let (method, seconds) = entry.split_once('=').ok_or_else(invalid)?;
+            let method = method.trim();
+            let seconds: u64 = seconds.trim().parse().map_err(|_| invalid())?;
+            if method.is_empty() || seconds == 0 {
+                return Err(invalid());
+            }
+            methods.insert(method.to_string(), Duration::from_secs(seconds));
+        }
+
+        Ok(Self {
+            default: config
+                .request_timeout
+                .filter(|seconds| *seconds > 0)
+                .map(Duration::from_secs),
+            methods,
+        })
+    }
+
+    /// Timeout of requests with the given method, if any
+    pub fn for_method(&self, method: &str) -> Option<Duration> {
+        self.methods.get(method).copied().or(self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_method_timeouts() {
+        let config = Config {
+            request_timeout: Some(30),
+            method_timeouts: vec!["tools/call=600".to_string(), " ping = 5".to_string()],
+            ..Default::default()
+        };
+        let timeouts = RequestTimeouts::from_config(&config).unwrap();
+        assert_eq!(
+            timeouts.for_method("tools/call"),
+            Some(Duration::from_secs(600))
+        );
+        assert_eq!(timeouts.for_method("ping"), Some(Duration::from_secs(5)));
+        assert_eq!(
+            timeouts.for_method("tools/list"),
+            Some(Duration::from_secs(30))
+        );
+
+        let timeouts = RequestTimeouts::from_config(&Config::default()).unwrap();
+        assert_eq!(timeouts.for_method("tools/call"), None);
+
+        for invalid in ["tools/call", "tools/call=soon", "=5", "ping=0"] {
+            let config = Config {
+                method_timeouts: vec![invalid.to_string()],
+                ..Default::default()
+            };
+            assert!(
+                RequestTimeouts::from_config(&config).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Retries of transient backend failures
+//!
+//! Network errors and `429`/`502`/`503`/`504` responses are retried with
+//! jittered exponential backoff, waiting for `Retry-After` when the backend
+//! sends one. Only requests that are safe to send twice are retried:
+//! `initialize`, `ping`, `*/list` and `resources/read`, plus `tools/call` for
+//! tools opted in with `--retry-tool`, since calling a tool may have side
+//! effects. Requests that exceed their `--request-timeout` are not retried.
+
+use crate::config::Config;
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::time::Duration;
+
+/// Upper bound of the backoff delay
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// Longest `Retry-After` the proxy waits for; longer ones are not retried
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Default number of retries
+pub const DEFAULT_RETRIES: u32 = 2;
+
+/// Default delay before the first retry
+pub const DEFAULT_BASE_DELAY_MS: u64 = 250;
+
+/// Which requests are retried and how often
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub base_delay: Duration,
+    /// Tools whose `tools/call` requests may be retried
+    pub tools: Vec<String>,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            retries: config.retries.unwrap_or(DEFAULT_RETRIES),
+            base_delay: Duration::from_millis(
+                config.retry_base_delay_ms.unwrap_or(DEFAULT_BASE_DELAY_MS),
+            ),
+            tools: config.retry_tools.clone(),
+        }
+    }
+
+    /// Whether the JSON-RPC request body may be sent again
+    ///
+    /// Batches qualify only if every message does.
+    pub fn is_retryable(&self, body: &[u8]) -> bool {
+        if self.retries == 0 {
+            return false;
+        }
+        match serde_json::from_slice::<Value>(body) {
+            Ok(Value::Array(messages)) => {
+                !messages.is_empty() && messages.iter().all(|m| self.is_retryable_message(m))
+            }
+            Ok(message) => self.is_retryable_message(&message),
+            Err(_) => false,
+        }
+    }
+
+    fn is_retryable_message(&self, message: &Value) -> bool {
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            return false;
+        };
+        match method {
+            "initialize" | "ping" | "resources/read" => true,
+            "tools/call" => message
+                .pointer("/params/name")
+                .and_then(Value::as_str)
+                .is_some_and(|name| self.tools.iter().any(|tool| tool == name)),
+            method => method.ends_with("/list"),
+        }
+    }
+
+    /// Delay before retry number `attempt` (0-based), `None` if not to retry
+    ///
+    /// `Retry-After` takes precedence over the backoff.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if attempt >= self.retries {
+            return None;
+        }
+        match retry_after {
+            Some(retry_after) if retry_after > MAX_RETRY_AFTER => None,
+            Some(retry_after) => Some(retry_after),
+            None => Some(self.backoff(attempt)),
+        }
+    }
+
+    /// Exponential backoff with equal jitter (between half and all of the delay)
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(MAX_DELAY);
+        let half = delay / 2;
+        half + half.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+}
+
+/// Whether a response status indicates a transient failure
+pub fn is_transient_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// `Retry-After` as delay (seconds or HTTP date)
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(delay.to_std().unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            retries: 3,
+            base_delay: Duration::from_millis(100),
+            tools: vec!["search".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_retryable_methods() {
+        let policy = policy();
+        for body in [
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}"#,
+            r#"{"jsonrpc": "2.0", "id": 2, "method": "tools/list"}"#,
+            r#"{"jsonrpc": "2.0", "id": 3, "method": "resources/templates/list"}"#,
+            r#"{"jsonrpc": "2.0", "id": 4, "method": "resources/read", "params": {"uri": "file:///a"}}"#,
+            r#"{"jsonrpc": "2.0", "id": 5, "method": "tools/call", "params": {"name": "search"}}"#,
+            r#"[{"jsonrpc": "2.0", "id": 6, "method": "ping"}, {"jsonrpc": "2.0", "id": 7, "method": "prompts/list"}]"#,
+        ] {
+            assert!(policy.is_retryable(body.as_bytes()), "{}", body);
+        }
+
+        for body in [
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": {"name": "delete_file"}}"#,
+            r#"{"jsonrpc": "2.0", "method": "notifications/initialized"}"#,
+            r#"[{"jsonrpc": "2.0", "id": 2, "method": "ping"}, {"jsonrpc": "2.0", "id": 3, "method": "tools/call", "params": {"name": "x"}}]"#,
+            r#"{"jsonrpc": "2.0", "id": 4, "result": {}}"#,
+            "not json",
+        ] {
+            assert!(!policy.is_retryable(body.as_bytes()), "{}", body);
+        }
+
+        let disabled = RetryPolicy {
+            retries: 0,
+            ..policy
+        };
+        assert!(!disabled.is_retryable(br#"{"jsonrpc": "2.0", "id": 1, "method": "ping"}"#));
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = policy();
+        for attempt in 0..3 {
+            let max = Duration::from_millis(100 * 2u64.pow(attempt));
+            let delay = policy.delay(attempt, None).unwrap();
+            assert!(delay >= max / 2 && delay <= max, "{:?}", delay);
+        }
+        assert!(policy.delay(3, None).is_none());
+
+        assert_eq!(
+            policy.delay(0, Some(Duration::from_secs(2))),
+            Some(Duration::from_secs(2))
+        );
+        assert!(policy.delay(0, Some(Duration::from_secs(3600))).is_none());
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert!(retry_after(&headers).is_none());
+
+        headers.insert(RETRY_AFTER, "7".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+
+        headers.insert(
+            RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+    }
+}
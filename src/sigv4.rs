@@ -0,0 +1,536 @@
+//! AWS Signature Version 4
+//!
+//! Signs backend requests for MCP servers behind IAM-protected API Gateway
+//! endpoints or Lambda function URLs. Credentials come from the standard
+//! environment variables (`AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`,
+//! `AWS_SESSION_TOKEN`) or, without them, from a profile in the shared
+//! credentials file (`~/.aws/credentials` or `AWS_SHARED_CREDENTIALS_FILE`).
+//! The file is read again when it changes or the backend answers 403, so
+//! temporary credentials refreshed by SSO or assume-role tooling are picked
+//! up. Credentials from the environment cannot change while the proxy runs.
+//!
+//! SigV4 uses the `Authorization` header itself, so a token added by the
+//! `--auth` provider is moved to `--sigv4-token-header` before signing.
+
+use crate::config::Config;
+use crate::error::{ProxyError, Result};
+use reqwest::header::{HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::Request;
+use ring::hmac;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+/// Signing algorithm identifier
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// Default service name (API Gateway)
+pub const DEFAULT_SERVICE: &str = "execute-api";
+
+/// Default header for the --auth token when signing (--sigv4-token-header)
+pub const DEFAULT_TOKEN_HEADER: &str = "x-authorization";
+
+const X_AMZ_DATE: &str = "x-amz-date";
+const X_AMZ_SECURITY_TOKEN: &str = "x-amz-security-token";
+
+/// AWS access key
+#[derive(Clone, PartialEq, Eq)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl std::fmt::Debug for AwsCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AwsCredentials")
+            .field("access_key_id", &self.access_key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AwsCredentials {
+    /// Credentials of a profile in a shared credentials file
+    fn from_file(path: &Path, profile: &str) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            ProxyError::Config(format!(
+                "No AWS credentials in the environment and {} is unreadable: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Self::from_credentials_file(&contents, profile).ok_or_else(|| {
+            ProxyError::Config(format!(
+                "No AWS credentials for profile '{}' in {}",
+                profile,
+                path.display()
+            ))
+        })
+    }
+
+    fn from_env() -> Option<Self> {
+        let var = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+        Some(Self {
+            access_key_id: var("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: var("AWS_SESSION_TOKEN"),
+        })
+    }
+
+    fn credentials_file() -> Result<PathBuf> {
+        if let Ok(path) = std::env::var("AWS_SHARED_CREDENTIALS_FILE") {
+            return Ok(PathBuf::from(path));
+        }
+        let home = std::env::var("HOME")
+            .or_else(|_| std::env::var("USERPROFILE"))
+            .map_err(|_| ProxyError::Config("Cannot determine home directory".to_string()))?;
+        Ok(PathBuf::from(home).join(".aws").join("credentials"))
+    }
+
+    /// Credentials of a profile in the INI format of `~/.aws/credentials`
+    fn from_credentials_file(contents: &str, profile: &str) -> Option<Self> {
+        let mut in_profile = false;
+        let mut values = BTreeMap::new();
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                in_profile = section.trim() == profile;
+            } else if in_profile {
+                if let Some((key, value)) = line.split_once('=') {
+                    values.insert(key.trim().to_string(), value.trim().to_string());
+                }
+            }
+        }
+
+        Some(Self {
+            access_key_id: values.remove("aws_access_key_id")?,
+            secret_access_key: values.remove("aws_secret_access_key")?,
+            session_token: values.remove("aws_session_token"),
+        })
+    }
+}
+
+/// Shared credentials file the signer's credentials were read from
+#[derive(Debug)]
+struct CredentialsFile {
+    path: PathBuf,
+    profile: String,
+    /// Modification time when last read
+    modified: RwLock<Option<SystemTime>>,
+}
+
+impl CredentialsFile {
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+}
+
+/// Signs requests for one region and service
+#[derive(Debug)]
+pub struct SigV4Signer {
+    credentials: RwLock<AwsCredentials>,
+    file: Option<CredentialsFile>,
+    region: String,
+    service: String,
+    token_header: HeaderName,
+}
+
+impl SigV4Signer {
+    pub fn new(credentials: AwsCredentials, region: &str, service: &str) -> Self {
+        Self {
+            credentials: RwLock::new(credentials),
+            file: None,
+            region: region.to_string(),
+            service: service.to_string(),
+            token_header: HeaderName::from_static(DEFAULT_TOKEN_HEADER),
+        }
+    }
+
+    /// Signer with credentials from a shared credentials file, re-read when it changes
+    fn from_credentials_file(
+        path: PathBuf,
+        profile: &str,
+        region: &str,
+        service: &str,
+    ) -> Result<Self> {
+        let file = CredentialsFile {
+            path,
+            profile: profile.to_string(),
+            modified: RwLock::new(None),
+        };
+        *file.modified.write().unwrap() = file.modified();
+        let credentials = AwsCredentials::from_file(&file.path, profile)?;
+
+        Ok(Self {
+            file: Some(file),
+            ..Self::new(credentials, region, service)
+        })
+    }
+
+    /// Signer for `--sigv4`
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let region = config.aws_region()?;
+        let service = config.aws_service.as_deref().unwrap_or(DEFAULT_SERVICE);
+        let mut signer = match AwsCredentials::from_env() {
+            Some(credentials) => Self::new(credentials, &region, service),
+            None => Self::from_credentials_file(
+                AwsCredentials::credentials_file()?,
+                config.aws_profile.as_deref().unwrap_or("default"),
+                &region,
+                service,
+            )?,
+        };
+        if let Some(ref header) = config.sigv4_token_header {
+            signer.token_header = HeaderName::from_bytes(header.as_bytes()).map_err(|_| {
+                ProxyError::Config(format!("Invalid SigV4 token header name '{}'", header))
+            })?;
+        }
+        Ok(signer)
+    }
+
+    /// Re-read the credentials file, returning whether the credentials changed
+    ///
+    /// Always `false` for credentials from the environment.
+    pub fn reload(&self) -> bool {
+        let Some(ref file) = self.file else {
+            return false;
+        };
+
+        *file.modified.write().unwrap() = file.modified();
+        match AwsCredentials::from_file(&file.path, &file.profile) {
+            Ok(credentials) => {
+                let mut current = self.credentials.write().unwrap();
+                let changed = *current != credentials;
+                if changed {
+                    tracing::info!("Reloaded AWS credentials from {}", file.path.display());
+                    *current = credentials;
+                }
+                changed
+            }
+            Err(e) => {
+                tracing::warn!("Failed to reload AWS credentials: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Current credentials, re-read first if the credentials file has changed
+    fn credentials(&self) -> AwsCredentials {
+        if let Some(ref file) = self.file {
+            if *file.modified.read().unwrap() != file.modified() {
+                self.reload();
+            }
+        }
+        self.credentials.read().unwrap().clone()
+    }
+
+    /// Sign the request at the current time
+    pub fn sign(&self, req: &mut Request) -> Result<()> {
+        self.sign_at(req, chrono::Utc::now())
+    }
+
+    fn sign_at(&self, req: &mut Request, now: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        let invalid = |e: reqwest::header::InvalidHeaderValue| {
+            ProxyError::Auth(format!("Invalid SigV4 header: {}", e))
+        };
+        let credentials = self.credentials();
+
+        // The --auth credential moves out of the way
+        if let Some(authorization) = req.headers_mut().remove(AUTHORIZATION) {
+            req.headers_mut()
+                .insert(self.token_header.clone(), authorization);
+        }
+
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = &amz_date[..8];
+        req.headers_mut()
+            .insert(X_AMZ_DATE, amz_date.parse().map_err(invalid)?);
+        if let Some(ref token) = credentials.session_token {
+            let mut value = HeaderValue::from_str(token).map_err(invalid)?;
+            value.set_sensitive(true);
+            req.headers_mut().insert(X_AMZ_SECURITY_TOKEN, value);
+        }
+
+        let (canonical_request, signed_headers) = canonical_request(req);
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+        let string_to_sign = format!(
+            "{}\n{}\n{}\n{}",
+            ALGORITHM,
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signing_key = hmac::Key::new(
+            hmac::HMAC_SHA256,
+            &self.signing_key(&credentials.secret_access_key, date),
+        );
+        let signature = hex(hmac::sign(&signing_key, string_to_sign.as_bytes()).as_ref());
+
+        let mut authorization = HeaderValue::from_str(&format!(
+            "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+            ALGORITHM, credentials.access_key_id, scope, signed_headers, signature
+        ))
+        .map_err(invalid)?;
+        authorization.set_sensitive(true);
+        req.headers_mut().insert(AUTHORIZATION, authorization);
+
+        Ok(())
+    }
+
+    /// Key derived from the secret for one day, region and service
+    fn signing_key(&self, secret_access_key: &str, date: &str) -> Vec<u8> {
+        let mac = |key: &[u8], data: &str| {
+            hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes())
+        };
+        let secret = format!("AWS4{}", secret_access_key);
+        let k_date = mac(secret.as_bytes(), date);
+        let k_region = mac(k_date.as_ref(), &self.region);
+        let k_service = mac(k_region.as_ref(), &self.service);
+        mac(k_service.as_ref(), "aws4_request").as_ref().to_vec()
+    }
+}
+
+/// Canonical request and the list of signed headers
+///
+/// Signs `host`, `content-type`, `x-amz-date` and `x-amz-security-token`;
+/// other headers may be changed by intermediaries without breaking the
+/// signature.
+fn canonical_request(req: &Request) -> (String, String) {
+    let url = req.url();
+
+    // Non-S3 services encode the (already encoded) path segments again
+    let path = if url.path().is_empty() {
+        "/"
+    } else {
+        url.path()
+    };
+    let canonical_uri = path
+        .split('/')
+        .map(uri_encode)
+        .collect::<Vec<_>>()
+        .join("/");
+
+    let mut query: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(name, value)| (uri_encode(&name), uri_encode(&value)))
+        .collect();
+    query.sort();
+    let canonical_query = query
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let mut headers = BTreeMap::new();
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    headers.insert("host", host);
+    for name in [CONTENT_TYPE.as_str(), X_AMZ_DATE, X_AMZ_SECURITY_TOKEN] {
+        if let Some(value) = req.headers().get(name).and_then(|v| v.to_str().ok()) {
+            headers.insert(name, value.split_whitespace().collect::<Vec<_>>().join(" "));
+        }
+    }
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let signed_headers = headers.keys().copied().collect::<Vec<_>>().join(";");
+
+    let payload_hash = match req.body() {
+        Some(body) => match body.as_bytes() {
+            Some(bytes) => hex(&Sha256::digest(bytes)),
+            None => "UNSIGNED-PAYLOAD".to_string(),
+        },
+        None => hex(&Sha256::digest(b"")),
+    };
+
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        req.method(),
+        canonical_uri,
+        canonical_query,
+        canonical_headers,
+        signed_headers,
+        payload_hash
+    );
+    (canonical_request, signed_headers)
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters
+fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Example from the AWS Signature Version 4 documentation
+    #[test]
+    fn test_sign_documented_example() {
+        let signer = SigV4Signer::new(
+            AwsCredentials {
+                access_key_id: "AKIDEXAMPLE".to_string(),
+                secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+                session_token: None,
+            },
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex(&signer.signing_key("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20150830")),
+            "c4afb1cc5771d871763a393e44b703571b55cc28424d1a5e86da6ed3c154a4b9"
+        );
+
+        let mut req = Request::new(
+            reqwest::Method::GET,
+            "https://iam.amazonaws.com/?Action=ListUsers&Version=2010-05-08"
+                .parse()
+                .unwrap(),
+        );
+        req.headers_mut().insert(
+            CONTENT_TYPE,
+            "application/x-www-form-urlencoded; charset=utf-8"
+                .parse()
+                .unwrap(),
+        );
+        let now = chrono::DateTime::parse_from_rfc3339("2015-08-30T12:36:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        signer.sign_at(&mut req, now).unwrap();
+
+        assert_eq!(req.headers()[X_AMZ_DATE], "20150830T123600Z");
+        assert_eq!(
+            req.headers()[AUTHORIZATION],
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+
+    #[test]
+    fn test_token_moved_and_session_token_signed() {
+        let signer = SigV4Signer::new(
+            AwsCredentials {
+                access_key_id: "AKIDEXAMPLE".to_string(),
+                secret_access_key: "secret".to_string(),
+                session_token: Some("session".to_string()),
+            },
+            "eu-west-1",
+            DEFAULT_SERVICE,
+        );
+        let mut req = Request::new(
+            reqwest::Method::POST,
+            "https://abc.execute-api.eu-west-1.amazonaws.com/prod/mcp"
+                .parse()
+                .unwrap(),
+        );
+        req.headers_mut()
+            .insert(AUTHORIZATION, "Bearer oidc-token".parse().unwrap());
+        signer.sign(&mut req).unwrap();
+
+        assert_eq!(req.headers()["x-authorization"], "Bearer oidc-token");
+        assert_eq!(req.headers()[X_AMZ_SECURITY_TOKEN], "session");
+        let authorization = req.headers()[AUTHORIZATION].to_str().unwrap();
+        assert!(authorization.contains("SignedHeaders=host;x-amz-date;x-amz-security-token"));
+    }
+
+    #[test]
+    fn test_credentials_file_profiles() {
+        let contents = "
+            [default]
+            aws_access_key_id = AKIDDEFAULT
+            aws_secret_access_key = secret-default
+
+            # comment
+            [mcp]
+            aws_access_key_id=AKIDMCP
+            aws_secret_access_key=secret-mcp
+            aws_session_token=token-mcp
+        ";
+        let mcp = AwsCredentials::from_credentials_file(contents, "mcp").unwrap();
+        assert_eq!(mcp.access_key_id, "AKIDMCP");
+        assert_eq!(mcp.session_token.as_deref(), Some("token-mcp"));
+        let default = AwsCredentials::from_credentials_file(contents, "default").unwrap();
+        assert_eq!(default.secret_access_key, "secret-default");
+        assert!(default.session_token.is_none());
+        assert!(AwsCredentials::from_credentials_file(contents, "other").is_none());
+    }
+
+    #[test]
+    fn test_credentials_file_is_reloaded() {
+        let dir = std::env::temp_dir().join(format!("sigv4-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("credentials");
+        let write = |access_key_id: &str, modified: u64| {
+            std::fs::write(
+                &path,
+                format!(
+                    "[mcp]\naws_access_key_id = {}\naws_secret_access_key = secret\naws_session_token = {}-token\n",
+                    access_key_id, access_key_id
+                ),
+            )
+            .unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(modified))
+                .unwrap();
+        };
+        let signed_key = |signer: &SigV4Signer| {
+            let mut req = Request::new(
+                reqwest::Method::POST,
+                "https://abc.execute-api.eu-west-1.amazonaws.com/mcp"
+                    .parse()
+                    .unwrap(),
+            );
+            signer.sign(&mut req).unwrap();
+            req.headers()[X_AMZ_SECURITY_TOKEN]
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+
+        write("AKID1", 1_000);
+        let signer =
+            SigV4Signer::from_credentials_file(path.clone(), "mcp", "eu-west-1", DEFAULT_SERVICE)
+                .unwrap();
+        assert_eq!(signed_key(&signer), "AKID1-token");
+        assert!(!signer.reload());
+
+        // Refreshed by external tooling
+        write("AKID2", 2_000);
+        assert_eq!(signed_key(&signer), "AKID2-token");
+
+        // Refreshed within the same modification time: reloaded on 403
+        write("AKID3", 2_000);
+        assert_eq!(signed_key(&signer), "AKID2-token");
+        assert!(signer.reload());
+        assert_eq!(signed_key(&signer), "AKID3-token");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}